@@ -1,14 +1,35 @@
+use super::error::Error;
 use bytes::BytesMut;
 use smol::channel::Sender;
+use std::time::Duration;
+
+// daemon 处理完行为后通过该通道返回结果
+pub(super) type Reply = Sender<Result<(), Error>>;
 
 #[derive(Debug)]
 pub(super) enum Action {
     Sub {
         sub_name: String,
+        id: u64,
         msg_sender: Sender<BytesMut>,
     },
+    // id 用于区分同一主题的不同订阅
+    UnSub {
+        sub_name: String,
+        id: u64,
+    },
     Pub {
         sub_name: String,
         payload: Vec<u8>,
+        // 是否等待服务器的 Ok/Err 后再返回结果
+        confirm: bool,
+    },
+    // 收到对应的 pong 后通过 pong_sender 返回往返时间
+    Ping {
+        pong_sender: Sender<Duration>,
+    },
+    // 关闭连接, drain 为 true 时先等待已发出的消息处理完
+    Close {
+        drain: bool,
     },
 }
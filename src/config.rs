@@ -0,0 +1,611 @@
+use super::client::Builder;
+use super::connector::ServerSelect;
+use super::error::Error;
+use super::proxy::Proxy;
+use super::tls::{TlsConfig, TlsPolicy};
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use std::env::{var, VarError};
+use std::fs::read;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ProxyProtocol {
+    Socks5,
+    Http,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct ProxyConfig {
+    pub protocol: ProxyProtocol,
+    pub host: String,
+    pub port: u16,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub username: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub password: Option<String>,
+}
+
+// 证书均从文件读取, 在转换为 Builder 时加载
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(default))]
+pub struct TlsFileConfig {
+    pub domain: Option<String>,
+    // pem 格式的 ca 证书文件
+    pub root_certificates: Vec<PathBuf>,
+    // pem 格式的客户端证书链与 pkcs#8 私钥
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub pkcs12_file: Option<PathBuf>,
+    pub pkcs12_password: Option<String>,
+    pub accept_invalid_certs: bool,
+}
+
+// 不借用任何数据的客户端配置, 可以从配置文件或环境变量加载
+// 时间均以毫秒为单位, 未设置的项使用 Builder 的默认值
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(default))]
+pub struct Config {
+    pub servers: Vec<ServerConfig>,
+    #[cfg(unix)]
+    pub unix_path: Option<PathBuf>,
+    #[cfg(feature = "websocket")]
+    pub websocket_url: Option<String>,
+    pub server_select: Option<ServerSelect>,
+    pub push: bool,
+    pub pull: bool,
+    pub max_message_total: Option<usize>,
+    pub tls_policy: Option<TlsPolicy>,
+    pub tls: Option<TlsFileConfig>,
+    pub connect_timeout_ms: Option<u64>,
+    pub tls_timeout_ms: Option<u64>,
+    pub info_timeout_ms: Option<u64>,
+    pub disable_reconnect: bool,
+    pub max_reconnect_attempts: Option<usize>,
+    pub reconnect_min_backoff_ms: Option<u64>,
+    pub reconnect_max_backoff_ms: Option<u64>,
+    pub ping_interval_ms: Option<u64>,
+    pub max_pings_outstanding: Option<usize>,
+    pub proxy: Option<ProxyConfig>,
+}
+
+impl Config {
+    // 读取 {prefix}SERVERS, {prefix}PUSH 等环境变量, 变量名为字段名的大写形式
+    // SERVERS 与 ROOT_CERTIFICATES 以逗号分隔, SERVERS 每项为 host:port
+    pub fn from_env(prefix: &str) -> Result<Self, Error> {
+        let get = |key: &str| -> Result<Option<String>, Error> {
+            match var(format!("{}{}", prefix, key)) {
+                Ok(value) => Ok(Some(value)),
+                Err(VarError::NotPresent) => Ok(None),
+                Err(VarError::NotUnicode(_)) => Err(config_error(key, "not unicode")),
+            }
+        };
+        let parse = |key: &str| -> Result<Option<u64>, Error> {
+            get(key)?.map(|value| parse_value(key, &value)).transpose()
+        };
+        let flag = |key: &str| -> Result<bool, Error> {
+            get(key)?
+                .map(|value| parse_flag(key, &value))
+                .unwrap_or(Ok(false))
+        };
+
+        let mut config = Config::default();
+
+        if let Some(servers) = get("SERVERS")? {
+            for server in servers.split(',').filter(|server| !server.is_empty()) {
+                let port_start = server
+                    .rfind(':')
+                    .ok_or_else(|| config_error("SERVERS", "missing port"))?;
+                config.servers.push(ServerConfig {
+                    host: server[..port_start]
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .to_string(),
+                    port: parse_value("SERVERS", &server[port_start + 1..])?,
+                });
+            }
+        }
+        #[cfg(unix)]
+        {
+            config.unix_path = get("UNIX_PATH")?.map(PathBuf::from);
+        }
+        #[cfg(feature = "websocket")]
+        {
+            config.websocket_url = get("WEBSOCKET_URL")?;
+        }
+        config.server_select = match get("SERVER_SELECT")?.as_deref() {
+            None => None,
+            Some("ordered") => Some(ServerSelect::Ordered),
+            Some("random") => Some(ServerSelect::Random),
+            Some(_) => return Err(config_error("SERVER_SELECT", "unknown value")),
+        };
+        config.push = flag("PUSH")?;
+        config.pull = flag("PULL")?;
+        config.max_message_total = parse("MAX_MESSAGE_TOTAL")?.map(|total| total as usize);
+
+        config.tls_policy = match get("TLS_POLICY")?.as_deref() {
+            None => None,
+            Some("disabled") => Some(TlsPolicy::Disabled),
+            Some("preferred") => Some(TlsPolicy::Preferred),
+            Some("required") => Some(TlsPolicy::Required),
+            Some(_) => return Err(config_error("TLS_POLICY", "unknown value")),
+        };
+        let tls = TlsFileConfig {
+            domain: get("TLS_DOMAIN")?,
+            root_certificates: get("TLS_ROOT_CERTIFICATES")?
+                .map(|files| {
+                    files
+                        .split(',')
+                        .filter(|file| !file.is_empty())
+                        .map(PathBuf::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            cert_file: get("TLS_CERT_FILE")?.map(PathBuf::from),
+            key_file: get("TLS_KEY_FILE")?.map(PathBuf::from),
+            pkcs12_file: get("TLS_PKCS12_FILE")?.map(PathBuf::from),
+            pkcs12_password: get("TLS_PKCS12_PASSWORD")?,
+            accept_invalid_certs: flag("TLS_ACCEPT_INVALID_CERTS")?,
+        };
+        if tls.domain.is_some()
+            || !tls.root_certificates.is_empty()
+            || tls.cert_file.is_some()
+            || tls.pkcs12_file.is_some()
+            || tls.accept_invalid_certs
+        {
+            config.tls = Some(tls);
+        }
+
+        config.connect_timeout_ms = parse("CONNECT_TIMEOUT_MS")?;
+        config.tls_timeout_ms = parse("TLS_TIMEOUT_MS")?;
+        config.info_timeout_ms = parse("INFO_TIMEOUT_MS")?;
+        config.disable_reconnect = flag("DISABLE_RECONNECT")?;
+        config.max_reconnect_attempts =
+            parse("MAX_RECONNECT_ATTEMPTS")?.map(|attempts| attempts as usize);
+        config.reconnect_min_backoff_ms = parse("RECONNECT_MIN_BACKOFF_MS")?;
+        config.reconnect_max_backoff_ms = parse("RECONNECT_MAX_BACKOFF_MS")?;
+        config.ping_interval_ms = parse("PING_INTERVAL_MS")?;
+        config.max_pings_outstanding = parse("MAX_PINGS_OUTSTANDING")?.map(|max| max as usize);
+
+        if let Some(host) = get("PROXY_HOST")? {
+            config.proxy = Some(ProxyConfig {
+                protocol: match get("PROXY_PROTOCOL")?.as_deref() {
+                    Some("socks5") => ProxyProtocol::Socks5,
+                    Some("http") => ProxyProtocol::Http,
+                    _ => return Err(config_error("PROXY_PROTOCOL", "unknown value")),
+                },
+                host,
+                port: get("PROXY_PORT")?
+                    .map(|port| parse_value("PROXY_PORT", &port))
+                    .transpose()?
+                    .ok_or_else(|| config_error("PROXY_PORT", "missing"))?,
+                username: get("PROXY_USERNAME")?,
+                password: get("PROXY_PASSWORD")?,
+            });
+        }
+
+        Ok(config)
+    }
+
+    // 读取证书文件失败时返回错误
+    pub fn builder(&self) -> Result<Builder<'_>, Error> {
+        // servers, unix_path 与 websocket_url 只能配置其中一种
+        #[allow(unused_mut)]
+        let mut transports = Vec::new();
+        if !self.servers.is_empty() {
+            transports.push("servers");
+        }
+        #[cfg(unix)]
+        if self.unix_path.is_some() {
+            transports.push("unix_path");
+        }
+        #[cfg(feature = "websocket")]
+        if self.websocket_url.is_some() {
+            transports.push("websocket_url");
+        }
+        if transports.len() > 1 {
+            return Err(config_error(
+                &transports.join(", "),
+                "only one transport can be configured",
+            ));
+        }
+
+        let mut servers = self.servers.iter();
+        let mut builder = match servers.next() {
+            Some(server) => Builder::new(&server.host, server.port),
+            None => self
+                .local_builder()
+                .ok_or_else(|| config_error("servers", "no server configured"))?,
+        };
+        for server in servers {
+            builder = builder.add_server(&server.host, server.port);
+        }
+
+        if let Some(select) = self.server_select {
+            builder = builder.set_server_select(select);
+        }
+        if self.push {
+            builder = builder.support_push();
+        }
+        if self.pull {
+            builder = builder.support_pull();
+        }
+        if let Some(total) = self.max_message_total {
+            if total == 0 {
+                return Err(config_error("max_message_total", "must be greater than 0"));
+            }
+            builder = builder.set_max_message_total(total);
+        }
+
+        if let Some(policy) = self.tls_policy {
+            builder = builder.set_tls_policy(policy);
+        }
+        if let Some(tls) = &self.tls {
+            builder = builder.set_tls_config(tls.load()?);
+        }
+
+        if let Some(ms) = self.connect_timeout_ms {
+            builder = builder.set_connect_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.tls_timeout_ms {
+            builder = builder.set_tls_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.info_timeout_ms {
+            builder = builder.set_info_timeout(Duration::from_millis(ms));
+        }
+
+        if self.disable_reconnect {
+            builder = builder.disable_reconnect();
+        }
+        if let Some(attempts) = self.max_reconnect_attempts {
+            builder = builder.set_max_reconnect_attempts(attempts);
+        }
+        if self.reconnect_min_backoff_ms.is_some() || self.reconnect_max_backoff_ms.is_some() {
+            builder = builder.set_reconnect_backoff(
+                Duration::from_millis(self.reconnect_min_backoff_ms.unwrap_or(100)),
+                Duration::from_millis(self.reconnect_max_backoff_ms.unwrap_or(30_000)),
+            );
+        }
+        if let Some(ms) = self.ping_interval_ms {
+            if ms == 0 {
+                return Err(config_error("ping_interval_ms", "must be greater than 0"));
+            }
+            builder = builder.set_ping_interval(Duration::from_millis(ms));
+        }
+        if let Some(max) = self.max_pings_outstanding {
+            if max == 0 {
+                return Err(config_error(
+                    "max_pings_outstanding",
+                    "must be greater than 0",
+                ));
+            }
+            builder = builder.set_max_pings_outstanding(max);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let mut proxy_builder = match proxy.protocol {
+                ProxyProtocol::Socks5 => Proxy::socks5(&proxy.host, proxy.port),
+                ProxyProtocol::Http => Proxy::http(&proxy.host, proxy.port),
+            };
+            if let Some(username) = &proxy.username {
+                proxy_builder =
+                    proxy_builder.set_auth(username, proxy.password.as_deref().unwrap_or(""));
+            }
+            builder = builder.set_proxy(proxy_builder);
+        }
+
+        Ok(builder)
+    }
+
+    // 未配置 servers 时使用 unix_path 或 websocket_url
+    fn local_builder(&self) -> Option<Builder<'_>> {
+        #[cfg(unix)]
+        if let Some(path) = &self.unix_path {
+            return Some(Builder::unix(path.as_path()));
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(url) = self.websocket_url.as_deref() {
+            return Some(Builder::websocket(url));
+        }
+        None
+    }
+}
+
+impl TlsFileConfig {
+    fn load(&self) -> Result<TlsConfig, Error> {
+        let mut config = TlsConfig::new();
+
+        if let Some(domain) = &self.domain {
+            config = config.set_domain(domain);
+        }
+        for file in &self.root_certificates {
+            config = config.add_root_certificate_pem(read(file)?);
+        }
+        match (&self.cert_file, &self.key_file, &self.pkcs12_file) {
+            (Some(cert), Some(key), None) => {
+                config = config.set_identity_pem(read(cert)?, read(key)?);
+            }
+            (None, None, Some(pkcs12)) => {
+                config = config.set_identity_pkcs12(
+                    read(pkcs12)?,
+                    self.pkcs12_password.as_deref().unwrap_or(""),
+                );
+            }
+            (None, None, None) => {}
+            _ => {
+                return Err(config_error(
+                    "tls",
+                    "set either cert_file with key_file or pkcs12_file",
+                ))
+            }
+        }
+        if self.accept_invalid_certs {
+            config = config.danger_accept_invalid_certs();
+        }
+
+        Ok(config)
+    }
+}
+
+fn config_error(key: &str, reason: &'static str) -> Error {
+    Error::Config {
+        key: key.to_string(),
+        reason,
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| config_error(key, "invalid number"))
+}
+
+fn parse_flag(key: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(config_error(key, "invalid boolean")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ProxyProtocol, ServerConfig};
+    use crate::connector::ServerSelect;
+    use crate::error::Error;
+    use crate::tls::TlsPolicy;
+    use std::env::set_var;
+
+    // 每个测试使用不同的前缀, 避免并行测试互相影响
+    fn from_env(prefix: &str, vars: &[(&str, &str)]) -> Result<Config, Error> {
+        for (key, value) in vars {
+            set_var(format!("{}{}", prefix, key), value);
+        }
+        Config::from_env(prefix)
+    }
+
+    fn env_error(prefix: &str, vars: &[(&str, &str)]) -> (String, &'static str) {
+        match from_env(prefix, vars) {
+            Err(Error::Config { key, reason }) => (key, reason),
+            other => panic!("expect config error, got {:?}", other),
+        }
+    }
+
+    fn config_error(config: &Config) -> (String, &'static str) {
+        match config.builder() {
+            Err(Error::Config { key, reason }) => (key, reason),
+            other => panic!("expect config error, got {:?}", other),
+        }
+    }
+
+    fn server() -> ServerConfig {
+        ServerConfig {
+            host: "localhost".to_string(),
+            port: 4222,
+        }
+    }
+
+    #[test]
+    fn builder_requires_a_transport() {
+        assert_eq!(config_error(&Config::default()).0, "servers");
+
+        let config = Config {
+            servers: vec![server()],
+            ..Config::default()
+        };
+        assert!(config.builder().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn builder_rejects_conflicting_transports() {
+        let config = Config {
+            servers: vec![server()],
+            unix_path: Some("/tmp/lizard.sock".into()),
+            ..Config::default()
+        };
+        assert_eq!(config_error(&config).0, "servers, unix_path");
+    }
+
+    #[test]
+    fn builder_rejects_zero_max_message_total() {
+        let config = Config {
+            servers: vec![server()],
+            max_message_total: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(config_error(&config).0, "max_message_total");
+    }
+
+    #[test]
+    fn builder_rejects_zero_heartbeat() {
+        let config = Config {
+            servers: vec![server()],
+            ping_interval_ms: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(config_error(&config).0, "ping_interval_ms");
+
+        let config = Config {
+            servers: vec![server()],
+            max_pings_outstanding: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(config_error(&config).0, "max_pings_outstanding");
+    }
+
+    #[test]
+    fn from_env_without_variables_is_default() {
+        let config = from_env("LIZARD_TEST_EMPTY_", &[]).unwrap();
+        assert!(config.servers.is_empty());
+        assert!(!config.push && !config.pull);
+        assert!(config.tls.is_none());
+        assert!(config.proxy.is_none());
+    }
+
+    #[test]
+    fn from_env_parses_values() {
+        let config = from_env(
+            "LIZARD_TEST_VALUES_",
+            &[
+                ("SERVERS", "[::1]:4222,localhost:4223,"),
+                ("SERVER_SELECT", "random"),
+                ("PUSH", "true"),
+                ("PULL", "0"),
+                ("MAX_MESSAGE_TOTAL", "32"),
+                ("TLS_POLICY", "required"),
+                ("TLS_DOMAIN", "example.com"),
+                ("TLS_ROOT_CERTIFICATES", "a.pem,b.pem"),
+                ("PING_INTERVAL_MS", "1500"),
+            ],
+        )
+        .unwrap();
+
+        let servers: Vec<_> = config
+            .servers
+            .iter()
+            .map(|server| (server.host.as_str(), server.port))
+            .collect();
+        assert_eq!(servers, vec![("::1", 4222), ("localhost", 4223)]);
+        assert_eq!(config.server_select, Some(ServerSelect::Random));
+        assert!(config.push);
+        assert!(!config.pull);
+        assert_eq!(config.max_message_total, Some(32));
+        assert_eq!(config.tls_policy, Some(TlsPolicy::Required));
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.domain.as_deref(), Some("example.com"));
+        assert_eq!(tls.root_certificates.len(), 2);
+        assert_eq!(config.ping_interval_ms, Some(1500));
+    }
+
+    #[test]
+    fn from_env_rejects_bad_values() {
+        assert_eq!(
+            env_error("LIZARD_TEST_BAD_SERVER_", &[("SERVERS", "localhost")]),
+            ("SERVERS".to_string(), "missing port")
+        );
+        assert_eq!(
+            env_error("LIZARD_TEST_BAD_PORT_", &[("SERVERS", "localhost:http")]),
+            ("SERVERS".to_string(), "invalid number")
+        );
+        assert_eq!(
+            env_error("LIZARD_TEST_BAD_FLAG_", &[("PUSH", "yes")]),
+            ("PUSH".to_string(), "invalid boolean")
+        );
+        assert_eq!(
+            env_error("LIZARD_TEST_BAD_POLICY_", &[("TLS_POLICY", "always")]),
+            ("TLS_POLICY".to_string(), "unknown value")
+        );
+        assert_eq!(
+            env_error("LIZARD_TEST_BAD_SELECT_", &[("SERVER_SELECT", "first")]),
+            ("SERVER_SELECT".to_string(), "unknown value")
+        );
+    }
+
+    #[test]
+    fn from_env_proxy_requires_protocol_and_port() {
+        assert_eq!(
+            env_error("LIZARD_TEST_PROXY_HOST_", &[("PROXY_HOST", "proxy")]),
+            ("PROXY_PROTOCOL".to_string(), "unknown value")
+        );
+        assert_eq!(
+            env_error(
+                "LIZARD_TEST_PROXY_PORT_",
+                &[("PROXY_HOST", "proxy"), ("PROXY_PROTOCOL", "socks5")]
+            ),
+            ("PROXY_PORT".to_string(), "missing")
+        );
+
+        let config = from_env(
+            "LIZARD_TEST_PROXY_FULL_",
+            &[
+                ("PROXY_HOST", "proxy"),
+                ("PROXY_PROTOCOL", "http"),
+                ("PROXY_PORT", "3128"),
+                ("PROXY_USERNAME", "alice"),
+            ],
+        )
+        .unwrap();
+        let proxy = config.proxy.unwrap();
+        assert_eq!(proxy.protocol, ProxyProtocol::Http);
+        assert_eq!((proxy.host.as_str(), proxy.port), ("proxy", 3128));
+        assert_eq!(proxy.username.as_deref(), Some("alice"));
+        assert_eq!(proxy.password, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_sample_document() {
+        let config: Config = toml::from_str(
+            r#"
+            push = true
+            server_select = "random"
+            tls_policy = "preferred"
+            connect_timeout_ms = 2000
+
+            [[servers]]
+            host = "127.0.0.1"
+            port = 4222
+
+            [[servers]]
+            host = "::1"
+            port = 4223
+
+            [tls]
+            domain = "example.com"
+            root_certificates = ["ca.pem"]
+
+            [proxy]
+            protocol = "socks5"
+            host = "proxy"
+            port = 1080
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.servers.len(), 2);
+        assert_eq!(config.servers[1].host, "::1");
+        assert!(config.push);
+        assert!(!config.pull);
+        assert_eq!(config.server_select, Some(ServerSelect::Random));
+        assert_eq!(config.tls_policy, Some(TlsPolicy::Preferred));
+        assert_eq!(config.connect_timeout_ms, Some(2000));
+        assert_eq!(config.tls.unwrap().root_certificates.len(), 1);
+        assert_eq!(config.proxy.unwrap().protocol, ProxyProtocol::Socks5);
+    }
+}
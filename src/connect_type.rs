@@ -1,15 +1,38 @@
-use async_native_tls::TlsStream;
+use super::tls::TlsStream;
 use smol::io::{AsyncRead, AsyncWrite};
+#[cfg(unix)]
+use smol::net::unix::UnixStream;
 use smol::net::TcpStream;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::io::Error as IoError;
-use std::marker::Unpin;
+use std::marker::{Send, Unpin};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-#[derive(Debug)]
+// 可以承载协议的任意双向字节流, 例如已经建立的 socket 或内存管道
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
 pub(super) enum ConnectType {
-    Tls(TlsStream<TcpStream>),
+    // tls 建立在其他任意一种连接之上
+    Tls(Box<TlsStream<ConnectType>>),
     Normal(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Custom(Box<dyn Transport>),
+}
+
+impl Debug for ConnectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Tls(tls_stream) => f.debug_tuple("Tls").field(tls_stream).finish(),
+            Self::Normal(stream) => f.debug_tuple("Normal").field(stream).finish(),
+            #[cfg(unix)]
+            Self::Unix(stream) => f.debug_tuple("Unix").field(stream).finish(),
+            Self::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
 }
 
 impl Unpin for ConnectType {}
@@ -21,8 +44,11 @@ impl AsyncRead for ConnectType {
         buf: &mut [u8],
     ) -> Poll<Result<usize, IoError>> {
         match self.get_mut() {
-            Self::Tls(tls_stream) => Pin::new(tls_stream).poll_read(cx, buf),
+            Self::Tls(tls_stream) => Pin::new(tls_stream.as_mut()).poll_read(cx, buf),
             Self::Normal(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Custom(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
         }
     }
 }
@@ -34,22 +60,31 @@ impl AsyncWrite for ConnectType {
         buf: &[u8],
     ) -> Poll<Result<usize, IoError>> {
         match self.get_mut() {
-            Self::Tls(tls_stream) => Pin::new(tls_stream).poll_write(cx, buf),
+            Self::Tls(tls_stream) => Pin::new(tls_stream.as_mut()).poll_write(cx, buf),
             Self::Normal(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Custom(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
         match self.get_mut() {
-            Self::Tls(tls_stream) => Pin::new(tls_stream).poll_flush(cx),
+            Self::Tls(tls_stream) => Pin::new(tls_stream.as_mut()).poll_flush(cx),
             Self::Normal(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Custom(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
         }
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
         match self.get_mut() {
-            Self::Tls(tls_stream) => Pin::new(tls_stream).poll_close(cx),
+            Self::Tls(tls_stream) => Pin::new(tls_stream.as_mut()).poll_close(cx),
             Self::Normal(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_close(cx),
+            Self::Custom(stream) => Pin::new(stream.as_mut()).poll_close(cx),
         }
     }
 }
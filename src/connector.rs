@@ -0,0 +1,320 @@
+use super::connect_type::ConnectType;
+use super::dial::dial_with_proxy;
+use super::error::{Error, HandShakeError, ServerFailures};
+use super::mode::Mode;
+use super::proxy::Proxy;
+use super::server_info::ServerInfo;
+use super::timeout::timeout;
+use super::tls::{self, TlsConfig, TlsPolicy};
+#[cfg(feature = "websocket")]
+use super::websocket;
+use log::warn;
+use protocol::send_to_server::{
+    decode::{Decode, Message},
+    encode::ClientConfig,
+};
+use protocol::state::Support;
+use smol::io::AsyncRead;
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(unix)]
+use smol::net::unix::UnixStream;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 服务器列表的选取顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ServerSelect {
+    // 按添加顺序依次尝试
+    #[default]
+    Ordered,
+    // 打乱顺序后依次尝试
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum ServerAddr {
+    Tcp {
+        host: String,
+        port: u16,
+    },
+    #[cfg(unix)]
+    Unix(PathBuf),
+    #[cfg(feature = "websocket")]
+    WebSocket(String),
+}
+
+impl ServerAddr {
+    fn host(&self) -> Option<&str> {
+        match self {
+            Self::Tcp { host, .. } => Some(host),
+            #[cfg(unix)]
+            Self::Unix(_) => None,
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(_) => None,
+        }
+    }
+}
+
+impl ServerAddr {
+    // 传输层本身是否加密, 例如 wss://
+    fn secure(&self) -> bool {
+        match self {
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(url) => url.starts_with("wss://"),
+            _ => false,
+        }
+    }
+}
+
+impl Display for ServerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+// 握手各阶段的超时时间, None 表示不限时
+#[derive(Debug, Clone)]
+pub(super) struct Timeouts {
+    pub(super) connect: Option<Duration>,
+    pub(super) tls: Option<Duration>,
+    pub(super) info: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(10)),
+            tls: Some(Duration::from_secs(10)),
+            info: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+// 建立连接所需的全部参数, daemon 断线重连时复用
+#[derive(Debug, Clone)]
+pub(super) struct Connector {
+    servers: Vec<ServerAddr>,
+    // 下一次连接优先尝试的服务器下标
+    next: usize,
+    tls_policy: TlsPolicy,
+    tls_config: TlsConfig,
+    support: u16,
+    timeouts: Timeouts,
+    proxy: Option<Proxy>,
+}
+
+pub(super) struct Connection {
+    pub(super) stream: ConnectType,
+    pub(super) decode: Decode,
+    pub(super) info: ServerInfo,
+    // Preferred 策略下服务器不支持 tls, 以明文连接
+    pub(super) tls_downgraded: bool,
+}
+
+impl Connector {
+    pub(super) fn new(
+        mut servers: Vec<ServerAddr>,
+        select: ServerSelect,
+        tls_policy: TlsPolicy,
+        tls_config: TlsConfig,
+        support: u16,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+    ) -> Self {
+        if select == ServerSelect::Random {
+            fastrand::shuffle(&mut servers);
+        }
+
+        Self {
+            servers,
+            next: 0,
+            tls_policy,
+            tls_config,
+            support,
+            timeouts,
+            proxy,
+        }
+    }
+
+    // 从上次成功的下一个服务器开始依次尝试, 全部失败才返回错误
+    pub(super) async fn connect(&mut self) -> Result<Connection, Error> {
+        let total = self.servers.len();
+        let mut failures = Vec::new();
+
+        for offset in 0..total {
+            let index = (self.next + offset) % total;
+            let addr = &self.servers[index];
+
+            match self.connect_server(addr).await {
+                Ok(mut connection) => {
+                    connection
+                        .info
+                        .set_tls(connection.info.tls() || addr.secure());
+                    connection.tls_downgraded &= !addr.secure();
+                    self.next = (index + 1) % total;
+                    return Ok(connection);
+                }
+                Err(e) => failures.push((addr.to_string(), e)),
+            }
+        }
+
+        if failures.len() == 1 && total == 1 {
+            Err(failures.remove(0).1)
+        } else {
+            Err(Error::NoServerAvailable(ServerFailures::new(failures)))
+        }
+    }
+
+    async fn connect_server(&self, addr: &ServerAddr) -> Result<Connection, Error> {
+        let connect = timeout(self.timeouts.connect, self.open(addr))
+            .await
+            .ok_or(Error::HandShake(HandShakeError::ConnectTimeout))??;
+
+        self.handshake(connect, addr.host()).await
+    }
+
+    // 消息流程为 连接后服务器发送服务器信息, 客户端接收后发送客户端信息
+    // host 用于未设置 tls 域名时校验证书
+    pub(super) async fn handshake(
+        &self,
+        mut connect: ConnectType,
+        host: Option<&str>,
+    ) -> Result<Connection, Error> {
+        let mut decode = Decode::new(1024);
+
+        let (server_support, max_message_length) = timeout(
+            self.timeouts.info,
+            Self::wait_info(&mut connect, &mut decode),
+        )
+        .await
+        .ok_or(Error::HandShake(HandShakeError::InfoTimeout))??;
+
+        let mut config = ClientConfig::default();
+        if self.support & Support::Push {
+            config.support_push();
+        }
+        if self.support & Support::Pull {
+            config.support_pull();
+        }
+        connect.write(&config.encode()).await?;
+        connect.flush().await?;
+
+        let mode = Self::select_mode(&server_support, &self.support)?;
+        let stream = timeout(
+            self.timeouts.tls,
+            self.select_stream(&server_support, host, connect),
+        )
+        .await
+        .ok_or(Error::HandShake(HandShakeError::TlsTimeout))??;
+
+        let tls = matches!(stream, ConnectType::Tls(_));
+
+        Ok(Connection {
+            stream,
+            decode,
+            info: ServerInfo::new(server_support, max_message_length, tls, mode),
+            tls_downgraded: self.tls_policy == TlsPolicy::Preferred && !tls,
+        })
+    }
+
+    async fn open(&self, addr: &ServerAddr) -> Result<ConnectType, Error> {
+        match addr {
+            ServerAddr::Tcp { host, port } => {
+                let stream = dial_with_proxy(self.proxy.as_ref(), host, *port).await?;
+                Ok(ConnectType::Normal(stream))
+            }
+            #[cfg(unix)]
+            ServerAddr::Unix(path) => Ok(ConnectType::Unix(UnixStream::connect(path).await?)),
+            #[cfg(feature = "websocket")]
+            ServerAddr::WebSocket(url) => {
+                websocket::connect(url, &self.tls_config, self.proxy.as_ref()).await
+            }
+        }
+    }
+
+    // 返回服务器支持的功能与最大消息长度
+    async fn wait_info<S>(connect: &mut S, decode: &mut Decode) -> Result<(u16, u32), Error>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut buff = [0u8; 1024];
+
+        loop {
+            let size = connect.read(&mut buff).await?;
+
+            if size == 0 {
+                return Err(Error::HandShake(HandShakeError::ConnectClose));
+            } else {
+                decode.set_buff(&buff[..size]);
+
+                if let Some(message) = decode.iter().next() {
+                    if let Message::Info(info) = message? {
+                        return Ok((info.support, info.max_message_length));
+                    } else {
+                        return Err(Error::HandShake(HandShakeError::Parse));
+                    }
+                } else {
+                    continue;
+                }
+            }
+        }
+    }
+
+    fn select_mode(mask: &u16, support: &u16) -> Result<Mode, Error> {
+        if !(*support & Support::Push) && !(*support & Support::Pull) {
+            return Err(Error::HandShake(HandShakeError::ClientPushOrPull));
+        }
+
+        let tmp = *mask & *support;
+
+        if tmp & Support::Push && tmp & Support::Pull {
+            Ok(Mode::PushAndPull)
+        } else if tmp & Support::Push {
+            Ok(Mode::Push)
+        } else if tmp & Support::Pull {
+            Ok(Mode::Pull)
+        } else {
+            Err(Error::HandShake(HandShakeError::ServerPushOrPull))
+        }
+    }
+
+    async fn select_stream(
+        &self,
+        mask: &u16,
+        host: Option<&str>,
+        stream: ConnectType,
+    ) -> Result<ConnectType, Error> {
+        if self.tls_policy == TlsPolicy::Disabled {
+            return Ok(stream);
+        }
+
+        if *mask & Support::Tls {
+            let domain = self
+                .tls_config
+                .domain()
+                .or(host)
+                .ok_or(Error::HandShake(HandShakeError::TlsDomain))?;
+            let tls_stream = tls::connect(&self.tls_config, domain, stream).await?;
+            Ok(ConnectType::Tls(Box::new(tls_stream)))
+        } else if self.tls_policy == TlsPolicy::Required {
+            Err(Error::HandShake(HandShakeError::TlsNotSupported))
+        } else {
+            // 调用方会另外发送 Event::TlsDowngraded
+            warn!("server not support tls, fall back to plaintext");
+            Ok(stream)
+        }
+    }
+}
@@ -1,110 +1,318 @@
-use super::action::Action;
+use super::action::{Action, Reply};
 use super::connect_type::ConnectType;
-use super::error::Error;
+use super::connector::{Connection, Connector};
+use super::error::{Error, ServerError};
+use super::event::{Event, EventSender};
 use super::intval::Intval;
 use super::mode::Mode;
+use super::reconnect::Reconnect;
+use super::shared::Shared;
+use super::subject::SubjectIndex;
 use bytes::{Buf, BytesMut};
 use futures::future::FutureExt;
-use futures::select;
+use futures::{pin_mut, select};
+use log::{debug, warn};
 use protocol::send_to_server::{
     decode::{Decode, Message},
-    encode::{Err, Ok, Ping, Pong, Pub, Sub, TurnPull, TurnPush, UnSub},
+    encode::{Err, Ok, Ping, Pong, Pub, Sub, UnSub},
 };
-use smol::block_on;
-use smol::channel::{bounded, Receiver, Sender};
+use smol::channel::{bounded, Receiver, Sender, TrySendError};
+use smol::future;
 use smol::io::{AsyncReadExt, AsyncWriteExt};
-use std::collections::HashMap;
+use smol::Timer;
+use std::collections::{HashMap, VecDeque};
 use std::io::Error as IoError;
-use std::ops::Drop;
 use std::string::String;
-use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use waitgroup::Worker;
-use log::debug;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub(super) struct Daemon {
     mode: Mode,
     stream: ConnectType,
-    max_message_length: Arc<AtomicU32>,
+    // 与 Client 共享, 重连后更新
+    shared: Arc<Shared>,
 
     // 定时器
     intval: Intval,
+    // 已发送未收到 pong 的 ping, 服务器按顺序回复, 主动 ping 附带结果通道
+    pings: VecDeque<(Instant, Option<Sender<Duration>>)>,
 
-    client_recv: Receiver<(Action, Option<Worker>)>,
+    client_recv: Receiver<(Action, Option<Reply>)>,
 
-    // 订阅, 记录订阅与行为关系
-    sub_map: HashMap<String, Sender<BytesMut>>,
+    // 队列满时丢弃最旧的事件
+    event_sender: EventSender,
+
+    // 订阅, 记录订阅与行为关系, 同一主题的每个订阅者都收到一份消息
+    sub_map: HashMap<String, Vec<(u64, Sender<BytesMut>)>>,
+    // 按通配符匹配收到的消息属于哪些订阅
+    subject_index: SubjectIndex,
+
+    // 服务器按发送顺序对每个 sub, unsub 与 pub 回复 Ok 或 Err, 无人等待的请求以 None 占位
+    // 订阅请求同时记录主题, 被拒绝时移除本地订阅
+    pending_replies: VecDeque<(Option<String>, Option<Reply>)>,
+    // 主题的订阅仍在等待服务器回复时加入的订阅者, 随该订阅的回复一同返回
+    waiting_subs: HashMap<String, Vec<Reply>>,
+
+    // 排空时等待屏障 ping 的 pong, 收到后关闭连接并回复调用方
+    draining: Option<(Receiver<Duration>, Option<Reply>)>,
+    closed: bool,
+
+    // 断线重连
+    connector: Connector,
+    reconnect: Reconnect,
 }
 
 impl Daemon {
     pub(super) fn new(
-        mode: Mode,
         stream: ConnectType,
-        max_message_length: Arc<AtomicU32>,
-        client_recv: Receiver<(Action, Option<Worker>)>,
+        shared: Arc<Shared>,
+        client_recv: Receiver<(Action, Option<Reply>)>,
+        event_sender: EventSender,
+        intval: Intval,
+        connector: Connector,
+        reconnect: Reconnect,
     ) -> Self {
+        let mode = shared.server_info().mode();
+
         Self {
             mode,
             stream,
-            max_message_length,
-            intval: Intval::new(30),
+            shared,
+            intval,
+            pings: VecDeque::new(),
             client_recv,
+            event_sender,
             sub_map: HashMap::new(),
+            subject_index: SubjectIndex::default(),
+            pending_replies: VecDeque::new(),
+            waiting_subs: HashMap::new(),
+            draining: None,
+            closed: false,
+            connector,
+            reconnect,
         }
     }
 
     // 进行维持长连接活动
     pub(super) async fn run(mut self, mut decode: Decode) {
-        let mut buff = vec![0; self.max_message_length.load(Ordering::Acquire) as usize];
+        let mut buff = vec![0; self.max_message_length()];
         'main: loop {
-            select! {
+            let disconnect = select! {
                result = FutureExt::fuse(self.stream.read(&mut buff)) => {
                    match result {
-                       Ok(0) => {
-                          break 'main;
-                       },
+                       Ok(0) => true,
                        Ok(size) => {
                           self.decode_handle(&mut decode, &buff[..size]).await;
+                          false
                        },
                        Err(e) => {
-                          println!("decode {:?}", e);
+                          warn!("read from server failed {:?}", e);
+                          true
                        }
                    }
                },
-               result = FutureExt::fuse(self.client_recv.recv()) => {
+               result = FutureExt::fuse(Self::next_action(&self.client_recv, self.draining.is_some())) => {
                   match result {
-                      Ok((action, worker)) => {
-                          if let Err(e) = self.match_action(action, worker).await {
-                            println!("{:?}", e);
-                          }
+                      Some((action, reply)) => {
+                          self.match_action(action, reply).await;
+                          false
                       }
-                      Err(_) => {
-                          break;
+                      None => {
+                          // Client 未调用 close 就被丢弃
+                          if let Err(e) = self.shutdown().await {
+                              warn!("close connection failed {:?}", e);
+                          }
+                          break 'main;
                       }
                   }
                },
+               _ = FutureExt::fuse(Self::drained(&self.draining)) => {
+                  if let Some((_, reply)) = self.draining.take() {
+                      let result = self.finish().await.map_err(Error::from);
+                      Self::send_reply(reply, result);
+                  }
+                  break 'main;
+               },
                _ =  FutureExt::fuse(self.intval.run()) => {
-                  if let Err(e) = self.send_ping().await {
-
+                  if self.intval.tick() {
+                      if let Err(e) = self.send_ping(None).await {
+                          warn!("send ping failed {:?}", e);
+                      }
+                      false
+                  } else {
+                      // 半开的 tcp 连接不会返回错误, 只能依靠心跳发现
+                      warn!("server not reply ping, connection dead");
+                      true
                   }
                }
+            };
+
+            if self.closed {
+                break 'main;
+            }
+
+            if disconnect {
+                if let Some((_, reply)) = self.draining.take() {
+                    Self::send_reply(reply, Result::Err(Error::Disconnected));
+                    break 'main;
+                }
+
+                self.shared.set_connected(false);
+                self.fail_pending_replies();
+                self.pings.clear();
+                self.event_sender.send(Event::Disconnected);
+                match self.reconnect().await {
+                    Some(new_decode) => {
+                        decode = new_decode;
+                        buff.resize(self.max_message_length(), 0);
+                        self.shared.set_connected(true);
+                        self.event_sender.send(Event::Reconnected);
+                    }
+                    None => break 'main,
+                }
+            }
+        }
+    }
+
+    // 排空期间不再接收新的行为, 通道关闭后返回 None
+    async fn next_action(
+        client_recv: &Receiver<(Action, Option<Reply>)>,
+        draining: bool,
+    ) -> Option<(Action, Option<Reply>)> {
+        if draining {
+            future::pending().await
+        } else {
+            client_recv.recv().await.ok()
+        }
+    }
+
+    async fn drained(draining: &Option<(Receiver<Duration>, Option<Reply>)>) {
+        match draining {
+            Some((pong, _)) => {
+                let _ = pong.recv().await;
+            }
+            None => future::pending().await,
+        }
+    }
+
+    fn max_message_length(&self) -> usize {
+        self.shared.server_info().max_message_length() as usize
+    }
+
+    // 按退避策略重连, 重新握手并恢复所有订阅, 放弃重连时返回 None
+    // 等待期间继续接收行为, 所有 Client 与 Subscription 都已丢弃时放弃重连
+    async fn reconnect(&mut self) -> Option<Decode> {
+        let mut attempt = 0;
+
+        while self.reconnect.allow(attempt) {
+            let backoff = self.reconnect.backoff(attempt);
+            attempt += 1;
+
+            let result = {
+                let connect = FutureExt::fuse(Self::connect_after(&mut self.connector, backoff));
+                pin_mut!(connect);
+                loop {
+                    select! {
+                        result = connect => break result,
+                        action = FutureExt::fuse(self.client_recv.recv()) => match action {
+                            Result::Ok((action, reply)) => {
+                                if !Self::handle_offline(
+                                    &mut self.sub_map,
+                                    &mut self.subject_index,
+                                    action,
+                                    reply,
+                                ) {
+                                    return None;
+                                }
+                            }
+                            Result::Err(_) => return None,
+                        },
+                    }
+                }
+            };
+
+            let connection = match result {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("reconnect attempt {} failed {:?}", attempt, e);
+                    continue;
+                }
+            };
+
+            self.mode = connection.info.mode();
+            self.stream = connection.stream;
+            self.shared.set_server_info(connection.info);
+
+            if let Err(e) = self.resubscribe().await {
+                warn!("resubscribe after reconnect failed {:?}", e);
+                continue;
+            }
+
+            if connection.tls_downgraded {
+                self.event_sender.send(Event::TlsDowngraded);
             }
+            self.intval.reset();
+            return Some(connection.decode);
         }
+
+        None
+    }
+
+    async fn connect_after(
+        connector: &mut Connector,
+        backoff: Duration,
+    ) -> Result<Connection, Error> {
+        Timer::after(backoff).await;
+        connector.connect().await
+    }
+
+    // 重连期间收到的行为, 取消订阅只在本地生效, 恢复订阅时不再包含, 其余返回 Disconnected
+    // 关闭时连接已断开, 直接在本地结束所有订阅, 返回 false 表示放弃重连
+    fn handle_offline(
+        sub_map: &mut HashMap<String, Vec<(u64, Sender<BytesMut>)>>,
+        subject_index: &mut SubjectIndex,
+        action: Action,
+        reply: Option<Reply>,
+    ) -> bool {
+        match action {
+            Action::UnSub { sub_name, id } => {
+                Self::forget_sub(sub_map, subject_index, &sub_name, id);
+                Self::send_reply(reply, Result::Ok(()));
+            }
+            Action::Close { .. } => {
+                sub_map.clear();
+                subject_index.clear();
+                Self::send_reply(reply, Result::Ok(()));
+                return false;
+            }
+            _ => Self::send_reply(reply, Result::Err(Error::Disconnected)),
+        }
+        true
+    }
+
+    async fn resubscribe(&mut self) -> Result<(), IoError> {
+        let sub_names: Vec<String> = self.sub_map.keys().cloned().collect();
+        for sub_name in sub_names {
+            self.send_sub(&sub_name).await?;
+            self.waiting_subs.entry(sub_name.clone()).or_default();
+            self.pending_replies.push_back((Some(sub_name), None));
+        }
+        Ok(())
     }
 
     async fn decode_handle(&mut self, decode: &mut Decode, buff: &[u8]) {
-        decode.set_buff(&buff);
+        decode.set_buff(buff);
 
         for message_result in decode.iter() {
             match message_result {
                 Ok(message) => {
                     if let Err(e) = self.match_message(message).await {
-                        println!("decode error {:?}", e);
+                        warn!("handle message failed {:?}", e);
                     }
                 }
                 Err(e) => {
-                    println!("decode error {:?}", e);
+                    warn!("decode error {:?}", e);
                 }
             }
         }
@@ -113,10 +321,19 @@ impl Daemon {
     async fn match_message(&mut self, message: Message) -> Result<(), Error> {
         match message {
             Message::Ping => {
-                // self.stream.write(Ping::encode()).await?;
+                // 服务器的 ping 同样说明连接存活
+                self.send_pong().await?;
+                self.intval.reset();
             }
             Message::Pong => {
-                self.intval.reset().await;
+                self.intval.reset();
+                if let Some((sent, pong_sender)) = self.pings.pop_front() {
+                    let rtt = sent.elapsed();
+                    self.shared.record_rtt(rtt);
+                    if let Some(pong_sender) = pong_sender {
+                        let _ = pong_sender.try_send(rtt);
+                    }
+                }
             }
             Message::TurnPush => {
                 self.reply_turn_push().await?;
@@ -124,20 +341,57 @@ impl Daemon {
             Message::TurnPull => {
                 self.reply_turn_pull().await?;
             }
+            Message::Ok => {
+                if let Some((sub_name, reply)) = self.pending_replies.pop_front() {
+                    let waiting = sub_name.and_then(|sub_name| self.waiting_subs.remove(&sub_name));
+                    for reply in reply.into_iter().chain(waiting.into_iter().flatten()) {
+                        let _ = reply.try_send(Result::Ok(()));
+                    }
+                }
+            }
+            Message::Err(e) => {
+                let server_error = ServerError::parse(&String::from_utf8_lossy(&e.msg));
+                // 无人等待的请求被拒绝, 或错误不对应任何请求时发送到事件流
+                let (sub_name, reply) = self.pending_replies.pop_front().unwrap_or((None, None));
+                if let Some(sub_name) = sub_name {
+                    // 订阅被拒绝, 丢弃发送端使该主题的订阅者结束, 等待中的订阅者都收到错误
+                    self.sub_map.remove(&sub_name);
+                    self.subject_index.remove(&sub_name);
+                    for waiting in self.waiting_subs.remove(&sub_name).into_iter().flatten() {
+                        let _ = waiting.try_send(Result::Err(Error::Server(server_error.clone())));
+                    }
+                }
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.try_send(Result::Err(Error::Server(server_error)));
+                    }
+                    None => {
+                        warn!("server error {}", server_error);
+                        self.event_sender.send(Event::ServerError(server_error));
+                    }
+                }
+            }
             Message::Msg(msg) => {
                 debug!("msg {:?}", msg);
                 let sub_name = String::from_utf8(msg.sub_name.to_vec())?;
                 let payload = msg.payload;
-                self.recv_msg(sub_name, payload).await;
+                self.recv_msg(sub_name, payload).await?;
             }
             _ => {}
         }
         Ok(())
     }
 
-    async fn send_ping(&mut self) -> Result<(), IoError> {
+    async fn send_ping(&mut self, pong_sender: Option<Sender<Duration>>) -> Result<(), IoError> {
         self.stream.write(Ping::encode()).await?;
         self.stream.flush().await?;
+        self.pings.push_back((Instant::now(), pong_sender));
+        Ok(())
+    }
+
+    async fn send_pong(&mut self) -> Result<(), IoError> {
+        self.stream.write(Pong::encode()).await?;
+        self.stream.flush().await?;
         Ok(())
     }
 
@@ -190,49 +444,221 @@ impl Daemon {
     }
 
     // 从服务器那边接受消息
-    async fn recv_msg<'a>(&mut self, sub_name: String, msg: BytesMut) {
-        if let Some(sender) = self.sub_map.get_mut(&sub_name) {
-            if let Err(_) = sender.send(msg).await {
-                self.sub_map.remove(&sub_name);
+    // 不等待订阅者, 否则订阅者在处理消息时调用 Client 会与 daemon 互相等待, 心跳也会停止
+    // 订阅者的队列已满时丢弃这条消息并发出 SlowConsumer 事件
+    async fn recv_msg(&mut self, sub_name: String, msg: BytesMut) -> Result<(), IoError> {
+        let mut closed = Vec::new();
+        for pattern in self.subject_index.matches(&sub_name) {
+            if let Some(subscribers) = self.sub_map.get(&pattern) {
+                for (id, sender) in subscribers {
+                    match sender.try_send(msg.clone()) {
+                        Result::Ok(()) => {}
+                        Result::Err(TrySendError::Full(_)) => {
+                            warn!("subscriber of {} is too slow, message dropped", pattern);
+                            self.event_sender.send(Event::SlowConsumer(pattern.clone()));
+                        }
+                        Result::Err(TrySendError::Closed(_)) => {
+                            closed.push((pattern.clone(), *id));
+                        }
+                    }
+                }
+            }
+        }
+
+        // 订阅者已丢弃但取消订阅尚未处理, 直接移除
+        for (pattern, id) in closed {
+            if self.remove_sub(pattern, id).await? {
+                self.pending_replies.push_back((None, None));
             }
         }
+        Result::Ok(())
     }
 
-    async fn match_action(
-        &mut self,
-        action: Action,
-        wait_group: Option<Worker>,
-    ) -> Result<(), Error> {
-        match action {
+    // 处理结果通过 reply 返回给调用方
+    // 发往服务器的 sub, unsub 与需要确认的 pub 在收到服务器回复后才返回
+    async fn match_action(&mut self, action: Action, reply: Option<Reply>) {
+        let mut pending_sub = None;
+        let result = match action {
             Action::Sub {
                 sub_name,
+                id,
                 msg_sender,
             } => {
-                self.set_sub(sub_name, msg_sender).await?;
-                if let Some(worker) = wait_group {
-                    drop(worker);
-                    debug!("drop worker finish");
+                pending_sub = Some(sub_name.clone());
+                let result = self.set_sub(sub_name.clone(), id, msg_sender).await;
+                // 同一主题的订阅仍在等待服务器回复, 排在其后返回
+                if let Result::Ok(false) = result {
+                    if let Some(waiting) = self.waiting_subs.get_mut(&sub_name) {
+                        waiting.extend(reply);
+                        return;
+                    }
                 }
+                result
             }
-            Action::Pub { sub_name, payload } => {
-                self.set_publish(sub_name, payload).await?;
-                if let Some(worker) = wait_group {
-                    drop(worker);
+            Action::UnSub { sub_name, id } => self.remove_sub(sub_name, id).await,
+            Action::Pub {
+                sub_name,
+                payload,
+                confirm,
+            } => {
+                let result = self.set_publish(sub_name, payload).await;
+                if result.is_ok() {
+                    // 服务器对每个 pub 都会回复, 不需要确认的 pub 以 None 占位并立即返回
+                    if confirm {
+                        self.pending_replies.push_back((None, reply));
+                        return;
+                    }
+                    self.pending_replies.push_back((None, None));
                 }
+                result.map(|()| false)
             }
+            Action::Ping { pong_sender } => self.send_ping(Some(pong_sender)).await.map(|()| false),
+            Action::Close { drain } => {
+                self.close(drain, reply).await;
+                return;
+            }
+        };
+
+        match result {
+            Result::Ok(true) => self.pending_replies.push_back((pending_sub, reply)),
+            Result::Ok(false) => Self::send_reply(reply, Result::Ok(())),
+            Result::Err(e) => Self::send_reply(reply, Result::Err(e.into())),
         }
+    }
 
-        Ok(())
+    fn send_reply(reply: Option<Reply>, result: Result<(), Error>) {
+        match reply {
+            Some(reply) => {
+                let _ = reply.try_send(result);
+            }
+            None => {
+                if let Err(e) = result {
+                    warn!("action failed {:?}", e);
+                }
+            }
+        }
+    }
+
+    // 直接关闭时立即取消订阅并断开, 排空时先以 ping 作为屏障
+    // 收到 pong 时服务器已处理完之前的发布, 取消订阅前发出的消息也已送达订阅者
+    async fn close(&mut self, drain: bool, reply: Option<Reply>) {
+        if !drain {
+            let result = self.shutdown().await.map_err(Error::from);
+            Self::send_reply(reply, result);
+            return;
+        }
+
+        let (pong_sender, pong) = bounded(1);
+        let result = match self.unsubscribe_all().await {
+            Result::Ok(()) => self.send_ping(Some(pong_sender)).await,
+            Result::Err(e) => Result::Err(e),
+        };
+
+        match result {
+            Result::Ok(()) => self.draining = Some((pong, reply)),
+            Result::Err(e) => {
+                self.closed = true;
+                Self::send_reply(reply, Result::Err(e.into()));
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<(), IoError> {
+        let result = self.unsubscribe_all().await;
+        let finish = self.finish().await;
+        result.and(finish)
+    }
+
+    // 丢弃订阅的发送端, 订阅者读完已缓存的消息后结束
+    async fn finish(&mut self) -> Result<(), IoError> {
+        self.closed = true;
+        self.sub_map.clear();
+        self.subject_index.clear();
+        self.stream.close().await
+    }
+
+    // 所有订阅合并为一个取消订阅消息发送
+    async fn unsubscribe_all(&mut self) -> Result<(), IoError> {
+        if self.sub_map.is_empty() {
+            return Result::Ok(());
+        }
+
+        let mut unsub = UnSub::new();
+        self.sub_map.keys().for_each(|sub_name| {
+            unsub.push(sub_name.as_bytes());
+        });
+
+        self.send_unsub(unsub.encode()).await?;
+        self.pending_replies.push_back((None, None));
+        Result::Ok(())
+    }
+
+    // 断线后等待中的回复不会再收到, 无法确定服务器是否已经处理
+    fn fail_pending_replies(&mut self) {
+        let waiting = self.waiting_subs.drain().flat_map(|(_, replies)| replies);
+        for reply in self
+            .pending_replies
+            .drain(..)
+            .filter_map(|(_, reply)| reply)
+            .chain(waiting)
+        {
+            let _ = reply.try_send(Result::Err(Error::Disconnected));
+        }
     }
 
+    // 返回是否向服务器发送了订阅
     async fn set_sub(
         &mut self,
         sub_name: String,
+        id: u64,
         subscription_sender: Sender<BytesMut>,
-    ) -> Result<(), IoError> {
-        self.send_sub(&sub_name).await?;
-        self.sub_map.insert(sub_name, subscription_sender);
-        Ok(())
+    ) -> Result<bool, IoError> {
+        // 服务器对每个主题只需要一次订阅
+        let send = !self.sub_map.contains_key(&sub_name);
+        if send {
+            self.send_sub(&sub_name).await?;
+            self.subject_index.insert(&sub_name);
+            self.waiting_subs.entry(sub_name.clone()).or_default();
+        }
+        self.sub_map
+            .entry(sub_name)
+            .or_default()
+            .push((id, subscription_sender));
+        Ok(send)
+    }
+
+    // 最后一个订阅者移除后才向服务器取消订阅, 订阅已被移除时不做处理
+    // 返回是否向服务器发送了取消订阅
+    async fn remove_sub(&mut self, sub_name: String, id: u64) -> Result<bool, IoError> {
+        if !Self::forget_sub(&mut self.sub_map, &mut self.subject_index, &sub_name, id) {
+            return Result::Ok(false);
+        }
+
+        let mut unsub = UnSub::new();
+        unsub.push(sub_name.as_bytes());
+        self.send_unsub(unsub.encode()).await?;
+        Result::Ok(true)
+    }
+
+    // 从本地移除订阅者, 返回该主题是否因此不再有订阅者
+    fn forget_sub(
+        sub_map: &mut HashMap<String, Vec<(u64, Sender<BytesMut>)>>,
+        subject_index: &mut SubjectIndex,
+        sub_name: &str,
+        id: u64,
+    ) -> bool {
+        let subscribers = match sub_map.get_mut(sub_name) {
+            Some(subscribers) => subscribers,
+            None => return false,
+        };
+        subscribers.retain(|(current, _)| *current != id);
+        if !subscribers.is_empty() {
+            return false;
+        }
+
+        sub_map.remove(sub_name);
+        subject_index.remove(sub_name);
+        true
     }
 
     async fn set_publish(&mut self, sub_name: String, payload: Vec<u8>) -> Result<(), IoError> {
@@ -240,16 +666,271 @@ impl Daemon {
     }
 }
 
-impl Drop for Daemon {
-    //  折构时尝试发送取消订阅到服务端
-    fn drop(&mut self) {
+#[cfg(all(test, unix))]
+mod tests {
+    use super::Daemon;
+    use crate::action::{Action, Reply};
+    use crate::connect_type::ConnectType;
+    use crate::connector::{Connector, ServerSelect, Timeouts};
+    use crate::error::Error;
+    use crate::event::{self, Event};
+    use crate::intval::Intval;
+    use crate::mode::Mode;
+    use crate::reconnect::Reconnect;
+    use crate::server_info::ServerInfo;
+    use crate::shared::Shared;
+    use crate::timeout::timeout;
+    use crate::tls::{TlsConfig, TlsPolicy};
+    use bytes::BytesMut;
+    use protocol::send_to_server::{
+        decode::{self, Decode, Message},
+        encode::{Ping, Pong},
+    };
+    use smol::channel::{bounded, Receiver, Sender};
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+    use smol::net::unix::UnixStream;
+    use smol::{block_on, spawn};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    type ActionSender = Sender<(Action, Option<Reply>)>;
+
+    // 未运行的 daemon 与内存管道的另一端, 发送行为的通道与事件流
+    fn daemon(reconnect: Reconnect) -> (Daemon, UnixStream, ActionSender, Receiver<Event>) {
+        let (client, server) = UnixStream::pair().unwrap();
+
+        let shared = Arc::new(Shared::new(ServerInfo::new(0, 1024, false, Mode::Push)));
+        let (sender, receiver) = bounded(1);
+        let (event_sender, events) = event::channel(8);
+        let connector = Connector::new(
+            Vec::new(),
+            ServerSelect::default(),
+            TlsPolicy::Disabled,
+            TlsConfig::new(),
+            0,
+            Timeouts::default(),
+            None,
+        );
+
+        let daemon = Daemon::new(
+            ConnectType::Unix(client),
+            shared,
+            receiver,
+            event_sender,
+            Intval::new(Duration::from_secs(30), 2),
+            connector,
+            reconnect,
+        );
+
+        (daemon, server, sender, events)
+    }
+
+    // 在内存管道的一端运行 daemon, 返回另一端, 发送行为的通道与事件流
+    fn start(reconnect: Reconnect) -> (UnixStream, ActionSender, Receiver<Event>) {
+        let (daemon, server, sender, events) = daemon(reconnect);
+        spawn(daemon.run(Decode::new(1024))).detach();
+        (server, sender, events)
+    }
+
+    // 假的服务器发送 ping, 期望收到 pong
+    #[test]
+    fn reply_server_ping_with_pong() {
         block_on(async {
-            let mut unsub = UnSub::new();
-            self.sub_map.keys().for_each(|sub_name| {
-                unsub.push(sub_name.as_bytes());
-            });
+            let mut reconnect = Reconnect::default();
+            reconnect.disable();
+            let (mut server, _sender, _events) = start(reconnect);
+
+            server.write_all(Ping::encode()).await.unwrap();
+            server.flush().await.unwrap();
+
+            let mut buff = vec![0; Pong::encode().len()];
+            timeout(Some(Duration::from_secs(5)), server.read_exact(&mut buff))
+                .await
+                .expect("no pong from client")
+                .unwrap();
+            assert_eq!(&buff[..], Pong::encode());
+        });
+    }
+
+    // 服务器不回复 ping 时, 达到上限后判定连接失效
+    #[test]
+    fn unanswered_pings_mark_connection_dead() {
+        block_on(async {
+            let (mut daemon, _server, _sender, events) = daemon(Reconnect::default());
+            daemon.intval = Intval::new(Duration::from_millis(50), 2);
+            spawn(daemon.run(Decode::new(1024))).detach();
+
+            let event = timeout(Some(Duration::from_secs(5)), events.recv())
+                .await
+                .expect("connection not marked dead");
+            assert!(matches!(event, Ok(Event::Disconnected)));
+        });
+    }
+
+    // 重连期间关闭不等待重连, 直接在本地完成
+    #[test]
+    fn close_while_reconnecting() {
+        block_on(async {
+            let (server, sender, events) = start(Reconnect::default());
+            drop(server);
+            assert!(matches!(events.recv().await, Ok(Event::Disconnected)));
+
+            let (reply, result) = bounded(1);
+            sender
+                .send((Action::Close { drain: true }, Some(reply)))
+                .await
+                .unwrap();
+
+            let result = timeout(Some(Duration::from_secs(5)), result.recv())
+                .await
+                .expect("close blocked by reconnect");
+            assert!(matches!(result, Ok(Ok(()))));
+        });
+    }
+
+    // 订阅者不读取时 daemon 不会阻塞, 多出的消息被丢弃并发出事件
+    #[test]
+    fn slow_subscriber_does_not_block() {
+        block_on(async {
+            let (mut daemon, _server, _sender, events) = daemon(Reconnect::default());
+            let (msg_sender, msgs) = bounded(1);
+            daemon
+                .set_sub("orders".to_string(), 1, msg_sender)
+                .await
+                .unwrap();
+
+            for payload in &["first", "second"] {
+                timeout(
+                    Some(Duration::from_secs(1)),
+                    daemon.recv_msg("orders".to_string(), BytesMut::from(*payload)),
+                )
+                .await
+                .expect("daemon blocked by subscriber")
+                .unwrap();
+            }
+
+            assert_eq!(msgs.try_recv().unwrap(), BytesMut::from("first"));
+            assert!(msgs.try_recv().is_err());
+            assert!(matches!(
+                events.try_recv(),
+                Ok(Event::SlowConsumer(ref pattern)) if pattern == "orders"
+            ));
+        });
+    }
+
+    fn server_err(text: &str) -> Message {
+        Message::Err(decode::Err {
+            msg: BytesMut::from(text),
+        })
+    }
+
+    async fn act(daemon: &mut Daemon, action: Action) -> Receiver<Result<(), Error>> {
+        let (reply, result) = bounded(1);
+        daemon.match_action(action, Some(reply)).await;
+        result
+    }
+
+    fn publish(confirm: bool) -> Action {
+        Action::Pub {
+            sub_name: "orders".to_string(),
+            payload: b"payload".to_vec(),
+            confirm,
+        }
+    }
+
+    fn subscribe(id: u64) -> (Action, Receiver<BytesMut>) {
+        let (msg_sender, msgs) = bounded(4);
+        let action = Action::Sub {
+            sub_name: "orders".to_string(),
+            id,
+            msg_sender,
+        };
+        (action, msgs)
+    }
+
+    // 同一主题的本地订阅共用一个服务器订阅, 后加入的订阅者等待同一个回复
+    #[test]
+    fn local_subscriptions_share_one_sub() {
+        block_on(async {
+            let (mut daemon, _server, _sender, _events) = daemon(Reconnect::default());
+
+            let (action, first_msgs) = subscribe(1);
+            let first = act(&mut daemon, action).await;
+            let (action, second_msgs) = subscribe(2);
+            let second = act(&mut daemon, action).await;
+            assert_eq!(daemon.pending_replies.len(), 1);
+            assert!(first.try_recv().is_err());
+            assert!(second.try_recv().is_err());
+
+            daemon.match_message(Message::Ok).await.unwrap();
+            assert!(matches!(first.try_recv(), Ok(Ok(()))));
+            assert!(matches!(second.try_recv(), Ok(Ok(()))));
+
+            daemon
+                .recv_msg("orders".to_string(), BytesMut::from("payload"))
+                .await
+                .unwrap();
+            assert_eq!(&first_msgs.try_recv().unwrap()[..], b"payload");
+            assert_eq!(&second_msgs.try_recv().unwrap()[..], b"payload");
+
+            let unsub = |id| Action::UnSub {
+                sub_name: "orders".to_string(),
+                id,
+            };
+            let first = act(&mut daemon, unsub(1)).await;
+            assert!(matches!(first.try_recv(), Ok(Ok(()))));
+            assert!(daemon.pending_replies.is_empty());
+            let second = act(&mut daemon, unsub(2)).await;
+            assert_eq!(daemon.pending_replies.len(), 1);
+
+            daemon.match_message(Message::Ok).await.unwrap();
+            assert!(matches!(second.try_recv(), Ok(Ok(()))));
+        });
+    }
+
+    // 订阅被拒绝时, 等待中的订阅者都收到错误且订阅结束
+    #[test]
+    fn rejected_sub_fails_every_waiting_subscriber() {
+        block_on(async {
+            let (mut daemon, _server, _sender, _events) = daemon(Reconnect::default());
+
+            let (action, first_msgs) = subscribe(1);
+            let first = act(&mut daemon, action).await;
+            let (action, second_msgs) = subscribe(2);
+            let second = act(&mut daemon, action).await;
+
+            daemon
+                .match_message(server_err("permission denied"))
+                .await
+                .unwrap();
+            assert!(matches!(first.try_recv(), Ok(Err(Error::Server(_)))));
+            assert!(matches!(second.try_recv(), Ok(Err(Error::Server(_)))));
+            assert!(first_msgs.recv().await.is_err());
+            assert!(second_msgs.recv().await.is_err());
+        });
+    }
+
+    // 服务器对每个 pub 按顺序回复, 不需要确认的 pub 也占一个位置
+    #[test]
+    fn replies_follow_publish_order() {
+        block_on(async {
+            let (mut daemon, _server, _sender, _events) = daemon(Reconnect::default());
+
+            let first = act(&mut daemon, publish(false)).await;
+            let second = act(&mut daemon, publish(true)).await;
+            let third = act(&mut daemon, publish(true)).await;
+            assert!(matches!(first.try_recv(), Ok(Ok(()))));
+            assert!(second.try_recv().is_err());
 
-            self.send_unsub(unsub.encode()).await;
+            daemon.match_message(Message::Ok).await.unwrap();
+            assert!(second.try_recv().is_err());
+            daemon.match_message(Message::Ok).await.unwrap();
+            assert!(matches!(second.try_recv(), Ok(Ok(()))));
+            daemon
+                .match_message(server_err("permission denied"))
+                .await
+                .unwrap();
+            assert!(matches!(third.try_recv(), Ok(Err(Error::Server(_)))));
         });
     }
 }
@@ -0,0 +1,125 @@
+use super::error::{ConnectError, Error};
+use super::proxy::Proxy;
+use futures::future::FutureExt;
+use futures::select;
+use futures::stream::{FuturesUnordered, StreamExt};
+use smol::net::{resolve, TcpStream};
+use smol::Timer;
+use std::io::Error as IoError;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// 上一个连接尝试未完成时, 启动下一个地址前的等待时间 (RFC 8305 建议 250ms)
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// 设置了代理时经由代理连接, 由代理负责解析域名
+pub(super) async fn dial_with_proxy(
+    proxy: Option<&Proxy>,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, Error> {
+    let stream = match proxy {
+        Some(proxy) => proxy.connect(host, port).await?,
+        None => dial(host, port).await?,
+    };
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// 解析域名后按 happy eyeballs 方式依次尝试所有地址, 返回最先建立的连接
+pub(super) async fn dial(host: &str, port: u16) -> Result<TcpStream, Error> {
+    let addrs = resolve((host, port)).await?;
+
+    let mut pending = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut failures = Vec::new();
+
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => attempts.push(attempt(addr)),
+                None => {
+                    return Err(Error::Connect(ConnectError::new(
+                        format!("{}:{}", host, port),
+                        failures,
+                    )));
+                }
+            }
+        }
+
+        select! {
+            (addr, result) = attempts.select_next_some() => {
+                match result {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => {
+                        failures.push((addr, e));
+                        if let Some(addr) = pending.next() {
+                            attempts.push(attempt(addr));
+                        }
+                    }
+                }
+            },
+            _ = FutureExt::fuse(Timer::after(ATTEMPT_DELAY)) => {
+                if let Some(addr) = pending.next() {
+                    attempts.push(attempt(addr));
+                }
+            }
+        }
+    }
+}
+
+async fn attempt(addr: SocketAddr) -> (SocketAddr, Result<TcpStream, IoError>) {
+    (addr, TcpStream::connect(addr).await)
+}
+
+// 以第一个地址的协议族开头, ipv6 与 ipv4 交替排列
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_v6 = addrs.first().map(SocketAddr::is_ipv6).unwrap_or(false);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == prefer_v6);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut result = Vec::new();
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (first, second) => {
+                result.extend(first);
+                result.extend(second);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interleave;
+    use std::net::SocketAddr;
+
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_starts_with_first_family() {
+        let input = addrs(&["[::1]:1", "[::2]:1", "127.0.0.1:1", "127.0.0.2:1"]);
+        let expect = addrs(&["[::1]:1", "127.0.0.1:1", "[::2]:1", "127.0.0.2:1"]);
+        assert_eq!(interleave(input), expect);
+
+        let input = addrs(&["127.0.0.1:1", "[::1]:1", "[::2]:1"]);
+        let expect = addrs(&["127.0.0.1:1", "[::1]:1", "[::2]:1"]);
+        assert_eq!(interleave(input), expect);
+    }
+
+    #[test]
+    fn interleave_keeps_single_family_order() {
+        let input = addrs(&["127.0.0.3:1", "127.0.0.1:1", "127.0.0.2:1"]);
+        assert_eq!(interleave(input.clone()), input);
+        assert!(interleave(Vec::new()).is_empty());
+    }
+}
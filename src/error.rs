@@ -1,7 +1,10 @@
-use async_native_tls::Error as TlsError;
+use super::tls::TlsError;
+#[cfg(feature = "websocket")]
+use async_tungstenite::tungstenite::Error as WsError;
 use protocol::send_to_server::decode::Error as DecodeError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::Error as IoError;
-use std::net::AddrParseError;
+use std::net::{AddrParseError, SocketAddr};
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
@@ -13,19 +16,64 @@ pub enum Error {
     #[error("io error `{0}`")]
     Io(#[from] IoError),
 
+    #[error("url parse error at `{component}`, because {reason}")]
+    Url {
+        component: String,
+        reason: &'static str,
+    },
+
+    #[error("config error at `{key}`, because {reason}")]
+    Config { key: String, reason: &'static str },
+
+    #[error("connect error, because `{0}`")]
+    Connect(ConnectError),
+
+    #[error("no server available, because `{0}`")]
+    NoServerAvailable(ServerFailures),
+
     #[error("parse error `{0}`")]
     Parse(#[from] DecodeError),
 
     #[error("tls error `{0}`")]
     Tls(#[from] TlsError),
 
+    #[cfg(feature = "websocket")]
+    #[error("websocket error `{0}`")]
+    WebSocket(Box<WsError>),
+
+    #[error("proxy error, because `{0}`")]
+    Proxy(ProxyError),
+
     #[error("hand shake error, because `{0}`")]
     HandShake(HandShakeError),
 
+    #[error("server rejected request, because `{0}`")]
+    Server(ServerError),
+
+    #[error("invalid subject `{0}`")]
+    InvalidSubject(String),
+
+    #[error("operation timeout")]
+    Timeout,
+
+    #[error("client already closed")]
+    Closed,
+
+    #[error("connection lost, reconnecting")]
+    Disconnected,
+
     #[error("convert utf8 error, because `{0}`")]
     Utf8(#[from] FromUtf8Error),
 }
 
+// tungstenite 的错误类型较大, 装箱后避免 Error 整体变大
+#[cfg(feature = "websocket")]
+impl From<WsError> for Error {
+    fn from(e: WsError) -> Self {
+        Self::WebSocket(Box::new(e))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum HandShakeError {
     #[error("tcp close")]
@@ -39,4 +87,193 @@ pub enum HandShakeError {
 
     #[error("server not select push or pull")]
     ServerPushOrPull,
+
+    #[error("tcp connect timeout")]
+    ConnectTimeout,
+
+    #[error("tls handshake timeout")]
+    TlsTimeout,
+
+    #[error("wait server info timeout")]
+    InfoTimeout,
+
+    #[error("tls required but server not support")]
+    TlsNotSupported,
+
+    #[error("tls domain not set and can not be taken from server address")]
+    TlsDomain,
+}
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("socks5 proxy not accept any auth method")]
+    Socks5NoAcceptableAuth,
+
+    #[error("socks5 proxy auth failed")]
+    Socks5AuthFailed,
+
+    #[error("socks5 proxy domain too long")]
+    Socks5Domain,
+
+    #[error("socks5 proxy connect failed, reply code `{0}`")]
+    Socks5Reply(u8),
+
+    #[error("socks5 proxy reply invalid")]
+    Socks5Invalid,
+
+    #[error("http proxy connect failed, `{0}`")]
+    HttpStatus(String),
+
+    #[error("http proxy reply invalid")]
+    HttpInvalid,
+}
+
+// 服务器通过 Err 消息返回的错误, 保留原始文本
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ServerError {
+    #[error("permission denied, `{0}`")]
+    PermissionDenied(String),
+
+    #[error("payload too large, `{0}`")]
+    PayloadTooLarge(String),
+
+    #[error("unknown subject, `{0}`")]
+    UnknownSubject(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ServerError {
+    // 协议的 Err 消息只携带文本, 没有错误码
+    // 只识别以固定短语开头的错误, 其余保留原文归为 Other, 避免按关键字误判
+    pub(super) fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let lower = text.to_ascii_lowercase();
+        let starts_with = |phrases: &[&str]| phrases.iter().any(|phrase| lower.starts_with(phrase));
+
+        if starts_with(&["permission denied"]) {
+            Self::PermissionDenied(text.to_string())
+        } else if starts_with(&["payload too large", "message too large"]) {
+            Self::PayloadTooLarge(text.to_string())
+        } else if starts_with(&["unknown subject"]) {
+            Self::UnknownSubject(text.to_string())
+        } else {
+            Self::Other(text.to_string())
+        }
+    }
+
+    // 服务器返回的原始错误文本
+    pub fn message(&self) -> &str {
+        match self {
+            Self::PermissionDenied(text)
+            | Self::PayloadTooLarge(text)
+            | Self::UnknownSubject(text)
+            | Self::Other(text) => text,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConnectError {
+    server: String,
+    failures: Vec<(SocketAddr, IoError)>,
+}
+
+impl ConnectError {
+    pub(super) fn new(server: String, failures: Vec<(SocketAddr, IoError)>) -> Self {
+        Self { server, failures }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    // 每个解析出的地址及其连接失败的原因
+    pub fn failures(&self) -> &[(SocketAddr, IoError)] {
+        &self.failures
+    }
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.failures.is_empty() {
+            return write!(f, "`{}` resolved to no address", self.server);
+        }
+
+        write!(f, "all addresses of `{}` failed:", self.server)?;
+        for (addr, e) in &self.failures {
+            write!(f, " {} ({})", addr, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+#[derive(Debug)]
+pub struct ServerFailures {
+    failures: Vec<(String, Error)>,
+}
+
+impl ServerFailures {
+    pub(super) fn new(failures: Vec<(String, Error)>) -> Self {
+        Self { failures }
+    }
+
+    // 每个服务器及其连接失败的原因, 按尝试顺序排列
+    pub fn failures(&self) -> &[(String, Error)] {
+        &self.failures
+    }
+}
+
+impl Display for ServerFailures {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "all servers failed:")?;
+        for (server, e) in &self.failures {
+            write!(f, " {} ({})", server, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ServerFailures {}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerError;
+
+    #[test]
+    fn parse_known_phrases() {
+        assert_eq!(
+            ServerError::parse("Permission denied for subject orders"),
+            ServerError::PermissionDenied("Permission denied for subject orders".to_string())
+        );
+        assert_eq!(
+            ServerError::parse(" payload too large \r\n"),
+            ServerError::PayloadTooLarge("payload too large".to_string())
+        );
+        assert_eq!(
+            ServerError::parse("message too large: 2048 > 1024"),
+            ServerError::PayloadTooLarge("message too large: 2048 > 1024".to_string())
+        );
+        assert_eq!(
+            ServerError::parse("unknown subject orders.new"),
+            ServerError::UnknownSubject("unknown subject orders.new".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_keeps_other_text() {
+        for text in &[
+            "unknown error while denied quota",
+            "403 forbidden",
+            "internal error: permission denied",
+            "",
+        ] {
+            let error = ServerError::parse(text);
+            assert_eq!(error, ServerError::Other(text.to_string()));
+            assert_eq!(error.message(), *text);
+        }
+    }
 }
@@ -0,0 +1,88 @@
+use super::error::ServerError;
+use smol::channel::{bounded, Receiver, Sender, TrySendError};
+use smol::stream::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// 不属于任何请求的连接事件
+#[derive(Debug, Clone)]
+pub enum Event {
+    // 无法对应到具体请求的服务器错误
+    ServerError(ServerError),
+    // 连接断开, 开始重连
+    Disconnected,
+    // 重连成功, 订阅已恢复
+    Reconnected,
+    // tls 策略为 Preferred 而服务器不支持 tls, 当前连接未加密
+    TlsDowngraded,
+    // 订阅者的消息队列已满, 发往该订阅 (主题) 的消息被丢弃
+    SlowConsumer(String),
+}
+
+// 事件队列满时丢弃最旧的事件, 保证最新的状态能被看到
+#[derive(Debug)]
+pub(super) struct EventSender {
+    sender: Sender<Event>,
+    oldest: Receiver<Event>,
+}
+
+pub(super) fn channel(capacity: usize) -> (EventSender, Receiver<Event>) {
+    let (sender, recv) = bounded(capacity);
+    let oldest = recv.clone();
+    (EventSender { sender, oldest }, recv)
+}
+
+impl EventSender {
+    pub(super) fn send(&self, mut event: Event) {
+        loop {
+            match self.sender.try_send(event) {
+                Err(TrySendError::Full(back)) => {
+                    let _ = self.oldest.try_recv();
+                    event = back;
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Events {
+    recv: Receiver<Event>,
+}
+
+impl Events {
+    pub(super) fn new(recv: Receiver<Event>) -> Self {
+        Self { recv }
+    }
+
+    // daemon 退出后返回 None
+    pub async fn next(&mut self) -> Option<Event> {
+        self.recv.recv().await.ok()
+    }
+}
+
+impl Stream for Events {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Stream::poll_next(Pin::new(&mut self.get_mut().recv), cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{channel, Event};
+
+    #[test]
+    fn full_queue_drops_oldest_event() {
+        let (sender, recv) = channel(2);
+        sender.send(Event::Disconnected);
+        sender.send(Event::Reconnected);
+        sender.send(Event::TlsDowngraded);
+
+        assert!(matches!(recv.try_recv(), Ok(Event::Reconnected)));
+        assert!(matches!(recv.try_recv(), Ok(Event::TlsDowngraded)));
+        assert!(recv.try_recv().is_err());
+    }
+}
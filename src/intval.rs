@@ -1,46 +1,66 @@
 use smol::Timer;
 use std::time::{Duration, Instant};
-use smol::Task;
-use smol::spawn;
-use std::mem::replace;
 
+// 心跳定时器, 记录已发送但未收到回复的 ping 数量
 #[derive(Debug)]
 pub(super) struct Intval {
-    count: u8,
-    init_intval: u64,
-    interval_task: Task<Instant>,
-    timeout_task: Task<Instant>,
+    interval: Duration,
+    max_outstanding: usize,
+    outstanding: usize,
+    deadline: Instant,
 }
 
 impl Intval {
-    pub(super) fn new(init_intval: u64) -> Self {
+    pub(super) fn new(interval: Duration, max_outstanding: usize) -> Self {
         Self {
-            count: 0,
-            init_intval: init_intval,
-            interval_task: spawn(Timer::at(Instant::now())),
-            timeout_task: spawn(Timer::at(Instant::now())),
+            interval,
+            max_outstanding,
+            outstanding: 0,
+            deadline: Instant::now() + interval,
         }
     }
 
-    // 推迟执行时间
-    pub(super) fn delay(&mut self) {
-        if self.count < 5 {
-            self.count += 1;
-        }
+    // 收到服务器的回应, 重新计时
+    pub(super) fn reset(&mut self) {
+        self.outstanding = 0;
+        self.deadline = Instant::now() + self.interval;
     }
 
-    // 重置执行
-    pub(super) async fn reset(&mut self) {
-        self.count = 0;
-        
-        let task = replace(&mut self.interval_task, spawn(Timer::at(Instant::now())));
+    // 定时器到期后调用, 未回复的 ping 已达上限时返回 false, 表示连接已失效
+    pub(super) fn tick(&mut self) -> bool {
+        if self.outstanding >= self.max_outstanding {
+            return false;
+        }
 
-        task.cancel().await;
+        self.outstanding += 1;
+        self.deadline = Instant::now() + self.interval;
+        true
     }
 
+    pub(super) async fn run(&self) -> Instant {
+        Timer::at(self.deadline).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Intval;
+    use std::time::Duration;
+
+    #[test]
+    fn dead_after_max_outstanding() {
+        let mut intval = Intval::new(Duration::from_secs(30), 2);
+        assert!(intval.tick());
+        assert!(intval.tick());
+        assert!(!intval.tick());
+    }
 
-    pub(super) async fn run(&mut self) -> Instant {
-        Timer::at(Instant::now() + Duration::from_secs(self.init_intval + (self.count * 10) as u64))
-            .await
+    #[test]
+    fn reset_clears_outstanding() {
+        let mut intval = Intval::new(Duration::from_secs(30), 1);
+        assert!(intval.tick());
+        intval.reset();
+        assert!(intval.tick());
+        assert!(!intval.tick());
     }
 }
@@ -1,13 +1,36 @@
 #![recursion_limit = "256"]
 mod action;
 mod client;
+mod config;
 mod connect_type;
+mod connector;
 mod daemon;
+mod dial;
 mod error;
+mod event;
 mod intval;
 mod mode;
+mod proxy;
+mod reconnect;
+mod rtt;
+mod server_info;
+mod shared;
+mod subject;
 mod subscription;
+mod timeout;
+mod tls;
+#[cfg(feature = "websocket")]
+mod websocket;
 
 pub use crate::client::{Builder, Client};
-pub use error::Error;
+pub use config::{Config, ProxyConfig, ProxyProtocol, ServerConfig, TlsFileConfig};
+pub use connect_type::Transport;
+pub use connector::ServerSelect;
+pub use error::{ConnectError, Error, HandShakeError, ProxyError, ServerError, ServerFailures};
+pub use event::{Event, Events};
+pub use mode::Mode;
+pub use proxy::Proxy;
+pub use rtt::Rtt;
+pub use server_info::ServerInfo;
 pub use subscription::Subscription;
+pub use tls::{TlsConfig, TlsError, TlsPolicy};
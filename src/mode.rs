@@ -1,12 +1,13 @@
-#[derive(Debug)]
-pub(super) enum Mode {
+// 客户端与服务器协商后的消息模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
     Push,
     Pull,
     PushAndPull,
 }
 
 impl Mode {
-    pub(super) fn can_push(&self) -> bool {
+    pub fn can_push(&self) -> bool {
         match self {
             Self::Push => true,
             Self::Pull => false,
@@ -14,7 +15,7 @@ impl Mode {
         }
     }
 
-    pub(super) fn can_pull(&self) -> bool {
+    pub fn can_pull(&self) -> bool {
         match self {
             Self::Push => false,
             Self::Pull => true,
@@ -0,0 +1,394 @@
+use super::dial::dial;
+use super::error::{Error, ProxyError};
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use smol::net::TcpStream;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+// 通过代理建立 tcp 连接, 握手与 tls 都在代理隧道内进行
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    kind: ProxyKind,
+    host: String,
+    port: u16,
+    auth: Option<(String, String)>,
+}
+
+impl Proxy {
+    pub fn socks5(host: &str, port: u16) -> Self {
+        Self::new(ProxyKind::Socks5, host, port)
+    }
+
+    // 使用 http CONNECT 方法建立隧道
+    pub fn http(host: &str, port: u16) -> Self {
+        Self::new(ProxyKind::Http, host, port)
+    }
+
+    fn new(kind: ProxyKind, host: &str, port: u16) -> Self {
+        Self {
+            kind,
+            host: host.to_string(),
+            port,
+            auth: None,
+        }
+    }
+
+    // socks5 使用用户名密码认证, http 使用 basic 认证
+    pub fn set_auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    pub(super) async fn connect(&self, host: &str, port: u16) -> Result<TcpStream, Error> {
+        let mut stream = dial(&self.host, self.port).await?;
+
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_handshake(&mut stream, host, port).await?,
+            ProxyKind::Http => self.http_handshake(&mut stream, host, port).await?,
+        }
+
+        Ok(stream)
+    }
+
+    async fn socks5_handshake(
+        &self,
+        stream: &mut TcpStream,
+        host: &str,
+        port: u16,
+    ) -> Result<(), Error> {
+        // 协商认证方式, 0x00 无需认证, 0x02 用户名密码
+        if self.auth.is_some() {
+            stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+        } else {
+            stream.write_all(&[0x05, 0x01, 0x00]).await?;
+        }
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 0x05 {
+            return Err(Error::Proxy(ProxyError::Socks5Invalid));
+        }
+
+        match (reply[1], &self.auth) {
+            (0x00, _) => {}
+            (0x02, Some((username, password))) => {
+                if username.len() > 255 || password.len() > 255 {
+                    return Err(Error::Proxy(ProxyError::Socks5AuthFailed));
+                }
+
+                let mut request = vec![0x01, username.len() as u8];
+                request.extend_from_slice(username.as_bytes());
+                request.push(password.len() as u8);
+                request.extend_from_slice(password.as_bytes());
+                stream.write_all(&request).await?;
+
+                // 子协商的版本号为 0x01
+                stream.read_exact(&mut reply).await?;
+                if reply[0] != 0x01 {
+                    return Err(Error::Proxy(ProxyError::Socks5Invalid));
+                }
+                if reply[1] != 0x00 {
+                    return Err(Error::Proxy(ProxyError::Socks5AuthFailed));
+                }
+            }
+            _ => return Err(Error::Proxy(ProxyError::Socks5NoAcceptableAuth)),
+        }
+
+        let mut request = vec![0x05, 0x01, 0x00];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(0x01);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(0x04);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                if host.len() > 255 {
+                    return Err(Error::Proxy(ProxyError::Socks5Domain));
+                }
+                request.push(0x03);
+                request.push(host.len() as u8);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 0x05 {
+            return Err(Error::Proxy(ProxyError::Socks5Invalid));
+        }
+        if reply[1] != 0x00 {
+            return Err(Error::Proxy(ProxyError::Socks5Reply(reply[1])));
+        }
+
+        // 跳过代理返回的绑定地址与端口
+        let addr_len = match reply[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len).await?;
+                len[0] as usize
+            }
+            _ => return Err(Error::Proxy(ProxyError::Socks5Invalid)),
+        };
+        let mut bind_addr = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bind_addr).await?;
+
+        Ok(())
+    }
+
+    async fn http_handshake(
+        &self,
+        stream: &mut TcpStream,
+        host: &str,
+        port: u16,
+    ) -> Result<(), Error> {
+        let authority = if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+        if let Some((username, password)) = &self.auth {
+            request.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64::encode(format!("{}:{}", username, password))
+            ));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // 逐字节读取响应头, 避免读走隧道内服务器发送的数据
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= 8192 {
+                return Err(Error::Proxy(ProxyError::HttpInvalid));
+            }
+            stream.read_exact(&mut byte).await?;
+            response.push(byte[0]);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        let mut parts = status_line.split_whitespace();
+
+        match (parts.next(), parts.next()) {
+            (Some(version), Some(status)) if version.starts_with("HTTP/") => {
+                if status.starts_with('2') {
+                    Ok(())
+                } else {
+                    Err(Error::Proxy(ProxyError::HttpStatus(
+                        status_line.to_string(),
+                    )))
+                }
+            }
+            _ => Err(Error::Proxy(ProxyError::HttpInvalid)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Proxy;
+    use crate::error::{Error, ProxyError};
+    use smol::channel::{bounded, Receiver};
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+    use smol::net::{TcpListener, TcpStream};
+    use smol::{block_on, spawn};
+
+    const USERNAME: &str = "alice";
+    const PASSWORD: &str = "secret";
+
+    // 隧道建立后把收到的字节原样返回
+    async fn echo(mut stream: TcpStream) {
+        let mut buff = [0u8; 1024];
+        loop {
+            match stream.read(&mut buff).await {
+                Ok(0) | Err(_) => break,
+                Ok(size) => {
+                    if stream.write_all(&buff[..size]).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // 假的 socks5 代理, 要求用户名密码认证, auth_version 为认证回复使用的版本号
+    // 通过通道返回客户端请求的目标地址
+    async fn socks5_server(auth_version: u8) -> (u16, Receiver<(String, u16)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, target) = bounded(1);
+
+        spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut greeting = [0u8; 4];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+            stream.write_all(&[0x05, 0x02]).await.unwrap();
+
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await.unwrap();
+            let mut username = vec![0u8; len[1] as usize];
+            stream.read_exact(&mut username).await.unwrap();
+            stream.read_exact(&mut len[..1]).await.unwrap();
+            let mut password = vec![0u8; len[0] as usize];
+            stream.read_exact(&mut password).await.unwrap();
+            let status = if username == USERNAME.as_bytes() && password == PASSWORD.as_bytes() {
+                0x00
+            } else {
+                0x01
+            };
+            stream.write_all(&[auth_version, status]).await.unwrap();
+            if status != 0x00 || auth_version != 0x01 {
+                return;
+            }
+
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..4], &[0x05, 0x01, 0x00, 0x03]);
+            let mut host = vec![0u8; request[4] as usize];
+            stream.read_exact(&mut host).await.unwrap();
+            let mut port = [0u8; 2];
+            stream.read_exact(&mut port).await.unwrap();
+            let _ = sender
+                .send((String::from_utf8(host).unwrap(), u16::from_be_bytes(port)))
+                .await;
+
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+            echo(stream).await;
+        })
+        .detach();
+
+        (port, target)
+    }
+
+    // 假的 http 代理, 以 status 回复 CONNECT 请求, 通过通道返回请求头
+    async fn http_server(status: &'static str) -> (u16, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, head) = bounded(1);
+
+        spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut byte = [0u8; 1];
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).await.unwrap();
+                request.push(byte[0]);
+            }
+            let _ = sender.send(String::from_utf8(request).unwrap()).await;
+
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            if status.starts_with('2') {
+                echo(stream).await;
+            }
+        })
+        .detach();
+
+        (port, head)
+    }
+
+    async fn assert_tunnel(mut stream: TcpStream) {
+        stream.write_all(b"hello").await.unwrap();
+        let mut buff = [0u8; 5];
+        stream.read_exact(&mut buff).await.unwrap();
+        assert_eq!(&buff, b"hello");
+    }
+
+    #[test]
+    fn socks5_connects_with_credentials() {
+        block_on(async {
+            let (port, target) = socks5_server(0x01).await;
+            let proxy = Proxy::socks5("127.0.0.1", port).set_auth(USERNAME, PASSWORD);
+
+            let stream = proxy.connect("broker.local", 4222).await.unwrap();
+            assert_eq!(
+                target.recv().await.unwrap(),
+                ("broker.local".to_string(), 4222)
+            );
+            assert_tunnel(stream).await;
+        });
+    }
+
+    #[test]
+    fn socks5_rejects_wrong_credentials() {
+        block_on(async {
+            let (port, _) = socks5_server(0x01).await;
+            let proxy = Proxy::socks5("127.0.0.1", port).set_auth(USERNAME, "wrong");
+
+            let result = proxy.connect("broker.local", 4222).await;
+            assert!(matches!(
+                result,
+                Err(Error::Proxy(ProxyError::Socks5AuthFailed))
+            ));
+        });
+    }
+
+    #[test]
+    fn socks5_rejects_bad_auth_version() {
+        block_on(async {
+            let (port, _) = socks5_server(0x05).await;
+            let proxy = Proxy::socks5("127.0.0.1", port).set_auth(USERNAME, PASSWORD);
+
+            let result = proxy.connect("broker.local", 4222).await;
+            assert!(matches!(
+                result,
+                Err(Error::Proxy(ProxyError::Socks5Invalid))
+            ));
+        });
+    }
+
+    #[test]
+    fn http_connects_with_credentials() {
+        block_on(async {
+            let (port, head) = http_server("200 Connection established").await;
+            let proxy = Proxy::http("127.0.0.1", port).set_auth(USERNAME, PASSWORD);
+
+            let stream = proxy.connect("::1", 4222).await.unwrap();
+            let head = head.recv().await.unwrap();
+            assert!(head.starts_with("CONNECT [::1]:4222 HTTP/1.1\r\n"));
+            assert!(head.contains(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64::encode("alice:secret")
+            )));
+            assert_tunnel(stream).await;
+        });
+    }
+
+    #[test]
+    fn http_rejects_non_success_status() {
+        block_on(async {
+            for status in &["407 Proxy Authentication Required", "502 Bad Gateway"] {
+                let (port, _) = http_server(status).await;
+                let proxy = Proxy::http("127.0.0.1", port).set_auth(USERNAME, "wrong");
+
+                match proxy.connect("broker.local", 4222).await {
+                    Err(Error::Proxy(ProxyError::HttpStatus(line))) => {
+                        assert_eq!(line, format!("HTTP/1.1 {}", status));
+                    }
+                    other => panic!("expect http status error, got {:?}", other),
+                }
+            }
+        });
+    }
+}
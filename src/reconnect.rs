@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+// 断线重连策略
+#[derive(Debug, Clone)]
+pub(super) struct Reconnect {
+    enable: bool,
+    max_attempts: Option<usize>,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            max_attempts: None,
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Reconnect {
+    pub(super) fn disable(&mut self) {
+        self.enable = false;
+    }
+
+    pub(super) fn set_max_attempts(&mut self, attempts: usize) {
+        self.max_attempts = Some(attempts);
+    }
+
+    pub(super) fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+    }
+
+    // 第 attempt 次重连是否还允许进行 (从 0 开始计数)
+    pub(super) fn allow(&self, attempt: usize) -> bool {
+        self.enable && self.max_attempts.is_none_or(|max| attempt < max)
+    }
+
+    // 指数退避, 在 [delay / 2, delay] 之间加入随机抖动
+    pub(super) fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32 << attempt.min(16);
+        let delay = self
+            .min_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        let half = delay / 2;
+        let jitter = fastrand::u64(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reconnect;
+    use std::time::Duration;
+
+    #[test]
+    fn allow_respects_disable_and_max_attempts() {
+        let mut reconnect = Reconnect::default();
+        assert!(reconnect.allow(1000));
+
+        reconnect.set_max_attempts(2);
+        assert!(reconnect.allow(1));
+        assert!(!reconnect.allow(2));
+
+        reconnect.disable();
+        assert!(!reconnect.allow(0));
+    }
+
+    #[test]
+    fn backoff_grows_within_jitter_bounds() {
+        let mut reconnect = Reconnect::default();
+        reconnect.set_backoff(Duration::from_millis(100), Duration::from_secs(1));
+
+        for (attempt, delay) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (64, 1000),
+        ] {
+            let backoff = reconnect.backoff(attempt);
+            let delay = Duration::from_millis(delay);
+            assert!(
+                backoff >= delay / 2 && backoff <= delay,
+                "{} {:?}",
+                attempt,
+                backoff
+            );
+        }
+    }
+
+    #[test]
+    fn backoff_max_not_below_min() {
+        let mut reconnect = Reconnect::default();
+        reconnect.set_backoff(Duration::from_millis(500), Duration::from_millis(100));
+        assert!(reconnect.backoff(0) >= Duration::from_millis(250));
+        assert!(reconnect.backoff(10) <= Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+// ping 往返时间, 尚未收到 pong 时为 None
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rtt {
+    latest: Option<Duration>,
+    smoothed: Option<Duration>,
+}
+
+impl Rtt {
+    // 与 tcp 的 srtt 相同, 新样本占 1/8 权重
+    pub(super) fn update(&mut self, sample: Duration) {
+        self.latest = Some(sample);
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => smoothed * 7 / 8 + sample / 8,
+            None => sample,
+        });
+    }
+
+    pub fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+}
@@ -0,0 +1,51 @@
+use super::mode::Mode;
+use protocol::state::Support;
+
+// 握手时服务器发送的信息以及协商结果, 重连后会更新
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    support: u16,
+    max_message_length: u32,
+    tls: bool,
+    mode: Mode,
+}
+
+impl ServerInfo {
+    pub(super) fn new(support: u16, max_message_length: u32, tls: bool, mode: Mode) -> Self {
+        Self {
+            support,
+            max_message_length,
+            tls,
+            mode,
+        }
+    }
+
+    pub(super) fn set_tls(&mut self, tls: bool) {
+        self.tls = tls;
+    }
+
+    pub fn support_push(&self) -> bool {
+        self.support & Support::Push
+    }
+
+    pub fn support_pull(&self) -> bool {
+        self.support & Support::Pull
+    }
+
+    pub fn support_tls(&self) -> bool {
+        self.support & Support::Tls
+    }
+
+    pub fn max_message_length(&self) -> u32 {
+        self.max_message_length
+    }
+
+    // 当前连接是否经过 tls 加密
+    pub fn tls(&self) -> bool {
+        self.tls
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+}
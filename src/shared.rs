@@ -0,0 +1,55 @@
+use super::rtt::Rtt;
+use super::server_info::ServerInfo;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+// Client 与 daemon 共享的连接状态, 由 daemon 更新
+#[derive(Debug)]
+pub(super) struct Shared {
+    server_info: Mutex<ServerInfo>,
+    // daemon 重连期间为 false
+    connected: AtomicBool,
+    rtt: Mutex<Rtt>,
+}
+
+impl Shared {
+    pub(super) fn new(server_info: ServerInfo) -> Self {
+        Self {
+            server_info: Mutex::new(server_info),
+            connected: AtomicBool::new(true),
+            rtt: Mutex::new(Rtt::default()),
+        }
+    }
+
+    pub(super) fn server_info(&self) -> ServerInfo {
+        lock(&self.server_info).clone()
+    }
+
+    pub(super) fn set_server_info(&self, server_info: ServerInfo) {
+        *lock(&self.server_info) = server_info;
+    }
+
+    pub(super) fn connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    pub(super) fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Release);
+    }
+
+    pub(super) fn rtt(&self) -> Rtt {
+        *lock(&self.rtt)
+    }
+
+    pub(super) fn record_rtt(&self, sample: Duration) {
+        lock(&self.rtt).update(sample);
+    }
+}
+
+// 持锁线程 panic 不影响这些简单数据的读写
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
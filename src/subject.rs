@@ -0,0 +1,200 @@
+use super::error::Error;
+use std::collections::HashMap;
+
+// 主题以 `.` 分隔为多个 token, `*` 匹配一个 token, `>` 只能在末尾, 匹配剩余的一个或多个 token
+pub(super) fn validate(pattern: &str) -> Result<(), Error> {
+    let invalid = || Error::InvalidSubject(pattern.to_string());
+
+    if pattern.is_empty() || pattern.chars().any(char::is_whitespace) {
+        return Err(invalid());
+    }
+
+    let tokens: Vec<&str> = pattern.split('.').collect();
+    for (index, token) in tokens.iter().enumerate() {
+        if token.is_empty() {
+            return Err(invalid());
+        }
+        if token.len() > 1 && (token.contains('*') || token.contains('>')) {
+            return Err(invalid());
+        }
+        if *token == ">" && index != tokens.len() - 1 {
+            return Err(invalid());
+        }
+    }
+
+    Ok(())
+}
+
+// 按 token 组织的前缀树, 用于查找与具体主题匹配的所有订阅
+#[derive(Debug, Default)]
+pub(super) struct SubjectIndex {
+    root: Node,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: HashMap<String, Node>,
+    // 以此节点结尾的订阅
+    pattern: Option<String>,
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.pattern.is_none() && self.children.is_empty()
+    }
+}
+
+impl SubjectIndex {
+    pub(super) fn insert(&mut self, pattern: &str) {
+        let node = pattern.split('.').fold(&mut self.root, |node, token| {
+            node.children.entry(token.to_string()).or_default()
+        });
+        node.pattern = Some(pattern.to_string());
+    }
+
+    pub(super) fn remove(&mut self, pattern: &str) {
+        let tokens: Vec<&str> = pattern.split('.').collect();
+        Self::remove_node(&mut self.root, &tokens);
+    }
+
+    // 返回子树是否已经为空, 为空时由上层删除
+    fn remove_node(node: &mut Node, tokens: &[&str]) -> bool {
+        match tokens.split_first() {
+            None => {
+                node.pattern = None;
+            }
+            Some((token, rest)) => {
+                if let Some(child) = node.children.get_mut(*token) {
+                    if Self::remove_node(child, rest) {
+                        node.children.remove(*token);
+                    }
+                }
+            }
+        }
+        node.is_empty()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.root = Node::default();
+    }
+
+    // 与主题匹配的所有订阅, 包括完全相同的订阅
+    pub(super) fn matches(&self, subject: &str) -> Vec<String> {
+        let tokens: Vec<&str> = subject.split('.').collect();
+        let mut result = Vec::new();
+        Self::collect(&self.root, &tokens, &mut result);
+        result
+    }
+
+    fn collect(node: &Node, tokens: &[&str], result: &mut Vec<String>) {
+        let (token, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => {
+                result.extend(node.pattern.iter().cloned());
+                return;
+            }
+        };
+
+        if let Some(child) = node.children.get(*token) {
+            Self::collect(child, rest, result);
+        }
+        // 主题本身就是通配符时上面已经按原样匹配过
+        if *token != "*" {
+            if let Some(child) = node.children.get("*") {
+                Self::collect(child, rest, result);
+            }
+        }
+        if *token != ">" {
+            if let Some(child) = node.children.get(">") {
+                result.extend(child.pattern.iter().cloned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, SubjectIndex};
+
+    fn sorted(mut patterns: Vec<String>) -> Vec<String> {
+        patterns.sort();
+        patterns
+    }
+
+    #[test]
+    fn star_matches_one_token_and_tail_matches_rest() {
+        let mut index = SubjectIndex::default();
+        index.insert("orders.*");
+        index.insert("orders.>");
+
+        assert_eq!(
+            sorted(index.matches("orders.new")),
+            vec!["orders.*", "orders.>"]
+        );
+        assert_eq!(index.matches("orders.new.eu"), vec!["orders.>"]);
+    }
+
+    #[test]
+    fn tail_does_not_match_bare_prefix() {
+        let mut index = SubjectIndex::default();
+        index.insert("orders.>");
+        index.insert("orders.*");
+
+        assert!(index.matches("orders").is_empty());
+    }
+
+    #[test]
+    fn overlapping_exact_and_wildcard() {
+        let mut index = SubjectIndex::default();
+        index.insert("orders.new");
+        index.insert("orders.*");
+        index.insert("*.new");
+        index.insert(">");
+
+        assert_eq!(
+            sorted(index.matches("orders.new")),
+            vec!["*.new", ">", "orders.*", "orders.new"]
+        );
+        assert_eq!(sorted(index.matches("orders.old")), vec![">", "orders.*"]);
+        assert_eq!(index.matches("users"), vec![">"]);
+    }
+
+    #[test]
+    fn remove_prunes_empty_nodes() {
+        let mut index = SubjectIndex::default();
+        index.insert("orders.new.eu");
+        index.insert("orders");
+
+        index.remove("orders.new.eu");
+        assert!(index.matches("orders.new.eu").is_empty());
+        assert!(!index.root.children["orders"].is_empty());
+        assert!(index.root.children["orders"].children.is_empty());
+
+        index.remove("orders");
+        assert!(index.root.is_empty());
+
+        // 移除不存在的订阅不影响其他订阅
+        index.insert("orders.*");
+        index.remove("orders.new");
+        assert_eq!(index.matches("orders.new"), vec!["orders.*"]);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in &["orders.*", "orders.>", ">", "*.new.*", "orders"] {
+            assert!(validate(pattern).is_ok(), "{}", pattern);
+        }
+        for pattern in &[
+            "",
+            "orders.",
+            ".orders",
+            "orders..new",
+            "orders.>.new",
+            "orders.n*",
+            "orders.>>",
+            "orders new",
+        ] {
+            assert!(validate(pattern).is_err(), "{:?}", pattern);
+        }
+    }
+}
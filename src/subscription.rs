@@ -1,9 +1,12 @@
+use super::action::{Action, Reply};
+use super::error::Error;
 use bytes::BytesMut;
-use smol::channel::Receiver;
+use smol::channel::{bounded, Receiver, Sender, TrySendError};
+use smol::spawn;
 use smol::stream::Stream;
 use std::borrow::Cow;
 use std::marker::Send;
-use std::ops::FnMut;
+use std::ops::{Drop, FnMut};
 use std::pin::Pin;
 use std::string::{FromUtf8Error, String};
 use std::task::{Context, Poll};
@@ -11,24 +14,58 @@ use std::task::{Context, Poll};
 #[derive(Debug)]
 pub struct Subscription {
     recv: Receiver<BytesMut>,
+    sub_name: String,
+    id: u64,
+    daemon_sender: Sender<(Action, Option<Reply>)>,
+    unsubscribed: bool,
 }
 
 impl Subscription {
-    pub(super) fn new(recv: Receiver<BytesMut>) -> Self {
-        Self { recv }
+    pub(super) fn new(
+        recv: Receiver<BytesMut>,
+        sub_name: String,
+        id: u64,
+        daemon_sender: Sender<(Action, Option<Reply>)>,
+    ) -> Self {
+        Self {
+            recv,
+            sub_name,
+            id,
+            daemon_sender,
+            unsubscribed: false,
+        }
+    }
+
+    pub fn sub_name(&self) -> &str {
+        &self.sub_name
+    }
+
+    // 取消订阅并等待服务器确认, 已缓存的消息随之丢弃
+    // 进入队列前被取消时由析构函数取消订阅
+    pub async fn unsubscribe(mut self) -> Result<(), Error> {
+        let (reply, result) = bounded(1);
+        self.daemon_sender
+            .send((self.unsub_action(), Some(reply)))
+            .await
+            .map_err(|_| Error::Closed)?;
+        self.unsubscribed = true;
+
+        result.recv().await.map_err(|_| Error::Closed)?
+    }
+
+    fn unsub_action(&self) -> Action {
+        Action::UnSub {
+            sub_name: self.sub_name.clone(),
+            id: self.id,
+        }
     }
 
     pub async fn with_bytes_handle<F>(self, mut proccess: F)
     where
         F: FnMut(BytesMut) + Send + 'static,
     {
-        loop {
-            match self.recv.recv().await {
-                Ok(msg) => proccess(msg),
-                Err(e) => {
-                    break;
-                }
-            }
+        while let Ok(msg) = self.recv.recv().await {
+            proccess(msg);
         }
     }
 
@@ -36,16 +73,9 @@ impl Subscription {
     where
         F: FnMut(Cow<'_, str>) + Send + 'static,
     {
-        loop {
-            match self.recv.recv().await {
-                Ok(msg) => {
-                    let msg_string = String::from_utf8_lossy(&msg);
-                    proccess(msg_string);
-                }
-                Err(e) => {
-                    break;
-                }
-            }
+        while let Ok(msg) = self.recv.recv().await {
+            let msg_string = String::from_utf8_lossy(&msg);
+            proccess(msg_string);
         }
     }
 
@@ -58,6 +88,25 @@ impl Subscription {
     }
 }
 
+impl Drop for Subscription {
+    // 析构时不能等待, 通道已满时交给后台任务发送
+    fn drop(&mut self) {
+        if self.unsubscribed {
+            return;
+        }
+
+        if let Err(TrySendError::Full(action)) =
+            self.daemon_sender.try_send((self.unsub_action(), None))
+        {
+            let daemon_sender = self.daemon_sender.clone();
+            spawn(async move {
+                let _ = daemon_sender.send(action).await;
+            })
+            .detach();
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BytesIter<'a> {
     iter: &'a mut Subscription,
@@ -88,3 +137,41 @@ impl<'a> Stream for StrIter<'a> {
             .map(|map| map.map(|item| String::from_utf8(item.to_vec())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Subscription;
+    use crate::action::Action;
+    use smol::channel::bounded;
+    use smol::{block_on, future};
+
+    // 等待进入队列时被取消, 析构函数仍会发送取消订阅
+    #[test]
+    fn cancelled_unsubscribe_still_unsubscribes() {
+        block_on(async {
+            let (daemon_sender, daemon_recv) = bounded(1);
+            let (_, recv) = bounded(1);
+            let subscription =
+                Subscription::new(recv, "orders".to_string(), 7, daemon_sender.clone());
+
+            // 占满通道, 使取消订阅无法进入队列
+            daemon_sender
+                .send((Action::Close { drain: false }, None))
+                .await
+                .unwrap();
+            future::or(async { subscription.unsubscribe().await.is_ok() }, async {
+                false
+            })
+            .await;
+
+            assert!(matches!(
+                daemon_recv.recv().await,
+                Ok((Action::Close { .. }, None))
+            ));
+            assert!(matches!(
+                daemon_recv.recv().await,
+                Ok((Action::UnSub { id: 7, .. }, None))
+            ));
+        });
+    }
+}
@@ -0,0 +1,20 @@
+use smol::future::{or, Future};
+use smol::Timer;
+use std::time::Duration;
+
+// 在限定时间内等待 future 完成, 超时返回 None, duration 为 None 时不限时
+pub(super) async fn timeout<F>(duration: Option<Duration>, future: F) -> Option<F::Output>
+where
+    F: Future,
+{
+    match duration {
+        Some(duration) => {
+            or(async { Some(future.await) }, async {
+                Timer::after(duration).await;
+                None
+            })
+            .await
+        }
+        None => Some(future.await),
+    }
+}
@@ -0,0 +1,135 @@
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+// 两种 tls 后端同时开启时使用 rustls
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+mod native;
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(super) use native::{connect, TlsStream};
+
+#[cfg(feature = "rustls")]
+mod rustls;
+#[cfg(feature = "rustls")]
+pub(super) use self::rustls::{connect, TlsStream};
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("one of the `native-tls` or `rustls` features must be enabled");
+
+// 服务器不支持 tls 时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum TlsPolicy {
+    // 不使用 tls
+    Disabled,
+    // 服务器支持时使用 tls, 否则发出警告并使用明文连接
+    Preferred,
+    // 必须使用 tls, 服务器不支持时握手失败
+    Required,
+}
+
+#[derive(Debug, Clone)]
+enum RootCertificate {
+    Pem(Vec<u8>),
+    Der(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+enum ClientIdentity {
+    // rustls 不支持 pkcs#12, 建立连接时返回错误
+    #[cfg_attr(feature = "rustls", allow(dead_code))]
+    Pkcs12 {
+        der: Vec<u8>,
+        password: String,
+    },
+    Pem {
+        cert: Vec<u8>,
+        key: Vec<u8>,
+    },
+}
+
+// tls 连接的证书与校验配置
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    root_certificates: Vec<RootCertificate>,
+    identity: Option<ClientIdentity>,
+    domain: Option<String>,
+    accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 信任额外的 ca 证书 (pem 格式)
+    pub fn add_root_certificate_pem(mut self, pem: Vec<u8>) -> Self {
+        self.root_certificates.push(RootCertificate::Pem(pem));
+        self
+    }
+
+    // 信任额外的 ca 证书 (der 格式)
+    pub fn add_root_certificate_der(mut self, der: Vec<u8>) -> Self {
+        self.root_certificates.push(RootCertificate::Der(der));
+        self
+    }
+
+    // 双向认证时提供的客户端证书 (pkcs#12 格式), rustls 后端建立连接时返回错误
+    pub fn set_identity_pkcs12(mut self, der: Vec<u8>, password: &str) -> Self {
+        self.identity = Some(ClientIdentity::Pkcs12 {
+            der,
+            password: password.to_string(),
+        });
+        self
+    }
+
+    // 双向认证时提供的客户端证书链与 pkcs#8 私钥 (pem 格式)
+    pub fn set_identity_pem(mut self, cert: Vec<u8>, key: Vec<u8>) -> Self {
+        self.identity = Some(ClientIdentity::Pem { cert, key });
+        self
+    }
+
+    // sni 与证书校验使用的域名, 未设置时使用连接地址
+    pub fn set_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    // 不校验服务器证书与域名, 仅用于测试环境
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.accept_invalid_certs = true;
+        self
+    }
+
+    pub(super) fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+}
+
+// 屏蔽具体 tls 后端的错误类型
+#[derive(Debug)]
+pub struct TlsError(Box<dyn StdError + Send + Sync>);
+
+impl TlsError {
+    pub(crate) fn new<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        Self(e.into())
+    }
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for TlsError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
@@ -0,0 +1,50 @@
+use super::{ClientIdentity, RootCertificate, TlsConfig, TlsError};
+use crate::error::Error;
+use async_native_tls::{Certificate, Identity, TlsConnector};
+use smol::io::{AsyncRead, AsyncWrite};
+
+pub(crate) use async_native_tls::TlsStream;
+
+pub(crate) async fn connect<S>(
+    config: &TlsConfig,
+    domain: &str,
+    stream: S,
+) -> Result<TlsStream<S>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let tls_stream = connector(config)
+        .map_err(TlsError::new)?
+        .connect(domain, stream)
+        .await
+        .map_err(TlsError::new)?;
+    Ok(tls_stream)
+}
+
+fn connector(config: &TlsConfig) -> Result<TlsConnector, async_native_tls::Error> {
+    let mut connector = TlsConnector::new();
+
+    for cert in &config.root_certificates {
+        let cert = match cert {
+            RootCertificate::Pem(pem) => Certificate::from_pem(pem)?,
+            RootCertificate::Der(der) => Certificate::from_der(der)?,
+        };
+        connector = connector.add_root_certificate(cert);
+    }
+
+    if let Some(identity) = &config.identity {
+        let identity = match identity {
+            ClientIdentity::Pkcs12 { der, password } => Identity::from_pkcs12(der, password)?,
+            ClientIdentity::Pem { cert, key } => Identity::from_pkcs8(cert, key)?,
+        };
+        connector = connector.identity(identity);
+    }
+
+    if config.accept_invalid_certs {
+        connector = connector
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    Ok(connector)
+}
@@ -0,0 +1,204 @@
+use super::{ClientIdentity, RootCertificate, TlsConfig, TlsError};
+use crate::error::Error;
+use async_tls::TlsConnector;
+use rustls_crate::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls_crate::{
+    Certificate, ClientConfig, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError,
+};
+use smol::io::{AsyncRead, AsyncWrite};
+use std::sync::Arc;
+use webpki::DNSNameRef;
+
+pub(crate) use async_tls::client::TlsStream;
+
+pub(crate) async fn connect<S>(
+    config: &TlsConfig,
+    domain: &str,
+    stream: S,
+) -> Result<TlsStream<S>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let connector = TlsConnector::from(Arc::new(client_config(config)?));
+    let tls_stream = connector
+        .connect(domain, stream)
+        .await
+        .map_err(TlsError::new)?;
+    Ok(tls_stream)
+}
+
+fn client_config(config: &TlsConfig) -> Result<ClientConfig, TlsError> {
+    let mut client_config = ClientConfig::new();
+    client_config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+    for cert in &config.root_certificates {
+        match cert {
+            RootCertificate::Pem(pem) => {
+                client_config
+                    .root_store
+                    .add_pem_file(&mut &pem[..])
+                    .map_err(|_| TlsError::new("invalid pem root certificate"))?;
+            }
+            RootCertificate::Der(der) => {
+                client_config
+                    .root_store
+                    .add(&Certificate(der.clone()))
+                    .map_err(TlsError::new)?;
+            }
+        }
+    }
+
+    match &config.identity {
+        Some(ClientIdentity::Pem { cert, key }) => {
+            let cert_chain = certs(&mut &cert[..])
+                .map_err(|_| TlsError::new("invalid pem client certificate"))?;
+            let key = pkcs8_private_keys(&mut &key[..])
+                .ok()
+                .and_then(|mut keys| keys.pop())
+                .or_else(|| rsa_private_keys(&mut &key[..]).ok()?.pop())
+                .ok_or_else(|| TlsError::new("invalid pem client private key"))?;
+            client_config
+                .set_single_client_cert(cert_chain, key)
+                .map_err(TlsError::new)?;
+        }
+        Some(ClientIdentity::Pkcs12 { .. }) => {
+            return Err(TlsError::new(
+                "pkcs#12 client identity is not supported by the rustls backend",
+            ));
+        }
+        None => {}
+    }
+
+    if config.accept_invalid_certs {
+        client_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerifier));
+    }
+
+    Ok(client_config)
+}
+
+// 不做任何校验, 仅用于 danger_accept_invalid_certs
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::connect;
+    use crate::error::Error;
+    use crate::tls::TlsConfig;
+    use async_tls::TlsAcceptor;
+    use rcgen::{BasicConstraints, Certificate as GenCertificate, CertificateParams, IsCa};
+    use rustls_crate::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+    use smol::net::{TcpListener, TcpStream};
+    use smol::{block_on, spawn};
+    use std::sync::Arc;
+
+    // 生成 ca 证书与由其签发的 localhost 证书, 返回 (ca der, 服务器配置)
+    fn server_config() -> (Vec<u8>, ServerConfig) {
+        let mut ca_params = CertificateParams::new(Vec::new());
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = GenCertificate::from_params(ca_params).unwrap();
+
+        let leaf =
+            GenCertificate::from_params(CertificateParams::new(vec!["localhost".to_string()]))
+                .unwrap();
+        let leaf_der = leaf.serialize_der_with_signer(&ca).unwrap();
+
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(
+                vec![Certificate(leaf_der)],
+                PrivateKey(leaf.serialize_private_key_der()),
+            )
+            .unwrap();
+
+        (ca.serialize_der().unwrap(), config)
+    }
+
+    // 启动只接受一个连接的 tls 服务器, 握手后发送 hello
+    async fn serve(config: ServerConfig) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut tls_stream) = acceptor.accept(stream).await {
+                let _ = tls_stream.write_all(b"hello").await;
+                let _ = tls_stream.flush().await;
+            }
+        })
+        .detach();
+
+        port
+    }
+
+    #[test]
+    fn handshake_with_trusted_root() {
+        block_on(async {
+            let (ca, config) = server_config();
+            let port = serve(config).await;
+
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let tls_config = TlsConfig::new().add_root_certificate_der(ca);
+            let mut tls_stream = connect(&tls_config, "localhost", stream).await.unwrap();
+
+            let mut buff = [0u8; 5];
+            tls_stream.read_exact(&mut buff).await.unwrap();
+            assert_eq!(&buff, b"hello");
+        });
+    }
+
+    #[test]
+    fn handshake_rejects_unknown_root() {
+        block_on(async {
+            let (_, config) = server_config();
+            let port = serve(config).await;
+
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            assert!(connect(&TlsConfig::new(), "localhost", stream)
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn pkcs12_identity_is_rejected() {
+        block_on(async {
+            let (_, config) = server_config();
+            let port = serve(config).await;
+
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let tls_config = TlsConfig::new().set_identity_pkcs12(Vec::new(), "");
+            let result = connect(&tls_config, "localhost", stream).await;
+            assert!(matches!(result, Err(Error::Tls(_))));
+        });
+    }
+
+    #[test]
+    fn handshake_accepts_invalid_certs_when_asked() {
+        block_on(async {
+            let (_, config) = server_config();
+            let port = serve(config).await;
+
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let tls_config = TlsConfig::new().danger_accept_invalid_certs();
+            assert!(connect(&tls_config, "localhost", stream).await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,281 @@
+use super::connect_type::ConnectType;
+use super::dial::dial_with_proxy;
+use super::error::Error;
+use super::proxy::Proxy;
+use super::tls::{self, TlsConfig};
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use async_tungstenite::tungstenite::error::{Error as WsError, UrlError};
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::{client_async, WebSocketStream};
+use futures::{Sink, Stream};
+use smol::io::{AsyncRead, AsyncWrite};
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// 建立 ws:// 或 wss:// 连接, 协议数据放在二进制帧中传输
+pub(super) async fn connect(
+    url: &str,
+    tls_config: &TlsConfig,
+    proxy: Option<&Proxy>,
+) -> Result<ConnectType, Error> {
+    let request = url.into_client_request()?;
+    let uri = request.uri();
+
+    let secure = match uri.scheme_str() {
+        Some("ws") => false,
+        Some("wss") => true,
+        _ => return Err(WsError::Url(UrlError::UnsupportedUrlScheme).into()),
+    };
+    let host = uri
+        .host()
+        .ok_or(WsError::Url(UrlError::NoHostName))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+    let tcp_stream = dial_with_proxy(proxy, &host, port).await?;
+
+    let stream = if secure {
+        let domain = tls_config.domain().unwrap_or(&host);
+        let tls_stream = tls::connect(tls_config, domain, ConnectType::Normal(tcp_stream)).await?;
+        ConnectType::Tls(Box::new(tls_stream))
+    } else {
+        ConnectType::Normal(tcp_stream)
+    };
+
+    let (ws_stream, _) = client_async(request, stream).await?;
+
+    Ok(ConnectType::Custom(Box::new(WsStream::new(ws_stream))))
+}
+
+// 把 websocket 的消息流转换为字节流
+struct WsStream {
+    inner: WebSocketStream<ConnectType>,
+    // 尚未读取完的二进制帧
+    read_buff: Vec<u8>,
+    read_pos: usize,
+}
+
+impl WsStream {
+    fn new(inner: WebSocketStream<ConnectType>) -> Self {
+        Self {
+            inner,
+            read_buff: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+fn into_io_error(e: WsError) -> IoError {
+    IoError::other(e)
+}
+
+impl AsyncRead for WsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let my = self.get_mut();
+
+        while my.read_pos >= my.read_buff.len() {
+            match Pin::new(&mut my.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Binary(data)))) => {
+                    my.read_buff = data;
+                    my.read_pos = 0;
+                }
+                Poll::Ready(Some(Ok(Message::Close(_)))) | Poll::Ready(None) => {
+                    return Poll::Ready(Ok(0));
+                }
+                // ping 由 tungstenite 自动回复, 其余帧忽略
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(into_io_error(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let remain = &my.read_buff[my.read_pos..];
+        let size = remain.len().min(buf.len());
+        buf[..size].copy_from_slice(&remain[..size]);
+        my.read_pos += size;
+
+        Poll::Ready(Ok(size))
+    }
+}
+
+impl AsyncWrite for WsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let mut inner = Pin::new(&mut self.get_mut().inner);
+
+        match inner.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        match inner.start_send(Message::Binary(buf.to_vec())) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(e) => Poll::Ready(Err(into_io_error(e))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::connect;
+    use crate::client::Builder;
+    use crate::error::{Error, HandShakeError};
+    use crate::tls::TlsConfig;
+    use async_tungstenite::accept_async;
+    use async_tungstenite::tungstenite::Message;
+    use futures::{SinkExt, StreamExt};
+    use smol::channel::{bounded, Receiver};
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+    use smol::net::{TcpListener, TcpStream};
+    use smol::{block_on, future, spawn};
+    use std::time::Duration;
+
+    // 假的 broker, 把收到的字节原样返回
+    async fn fake_broker() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buff = [0u8; 1024];
+            loop {
+                match stream.read(&mut buff).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => stream.write_all(&buff[..size]).await.unwrap(),
+                }
+            }
+        })
+        .detach();
+
+        port
+    }
+
+    // 不发送服务器信息的 broker, 收到连接时通知测试
+    async fn silent_broker() -> (u16, Receiver<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, accepted) = bounded(1);
+
+        spawn(async move {
+            // 保持连接直到测试结束
+            let (_stream, _) = listener.accept().await.unwrap();
+            let _ = sender.send(()).await;
+            future::pending::<()>().await;
+        })
+        .detach();
+
+        (port, accepted)
+    }
+
+    // websocket 服务器, 在二进制帧与 broker 的 tcp 字节流之间转发
+    async fn relay(broker_port: u16) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut ws_sink, mut ws_stream) = accept_async(stream).await.unwrap().split();
+            let broker = TcpStream::connect(("127.0.0.1", broker_port))
+                .await
+                .unwrap();
+            let (mut broker_read, mut broker_write) = (broker.clone(), broker);
+
+            let upstream = async {
+                while let Some(Ok(message)) = ws_stream.next().await {
+                    if let Message::Binary(data) = message {
+                        broker_write.write_all(&data).await.unwrap();
+                    }
+                }
+            };
+            let downstream = async {
+                let mut buff = [0u8; 1024];
+                loop {
+                    match broker_read.read(&mut buff).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(size) => {
+                            let message = Message::Binary(buff[..size].to_vec());
+                            if ws_sink.send(message).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            };
+            future::race(upstream, downstream).await;
+        })
+        .detach();
+
+        port
+    }
+
+    #[test]
+    fn bytes_round_trip_through_websocket() {
+        block_on(async {
+            let broker_port = fake_broker().await;
+            let port = relay(broker_port).await;
+
+            let url = format!("ws://127.0.0.1:{}/", port);
+            let mut stream = connect(&url, &TlsConfig::new(), None).await.unwrap();
+
+            stream.write_all(b"hello broker").await.unwrap();
+            stream.flush().await.unwrap();
+
+            let mut buff = [0u8; 12];
+            stream.read_exact(&mut buff).await.unwrap();
+            assert_eq!(&buff, b"hello broker");
+        });
+    }
+
+    // Builder 经 websocket 建立连接后在同一个流上等待服务器信息
+    #[test]
+    fn builder_handshakes_over_websocket() {
+        block_on(async {
+            let (broker_port, accepted) = silent_broker().await;
+            let port = relay(broker_port).await;
+
+            let url = format!("ws://127.0.0.1:{}/", port);
+            let result = Builder::websocket(&url)
+                .support_push()
+                .set_info_timeout(Duration::from_millis(300))
+                .connect()
+                .await;
+
+            assert!(accepted.try_recv().is_ok());
+            assert!(matches!(
+                result,
+                Err(Error::HandShake(HandShakeError::InfoTimeout))
+            ));
+        });
+    }
+
+    #[test]
+    fn reject_unsupported_scheme() {
+        block_on(async {
+            let result = connect("http://127.0.0.1:1/", &TlsConfig::new(), None).await;
+            assert!(result.is_err());
+        });
+    }
+}